@@ -11,6 +11,27 @@ use stellar_xdr::next::{
 
 mod ledger;
 
+/// Builds a ledger transition closing at the given sequence and time that
+/// carries the provided soroban events, each in its own sample transaction.
+///
+/// This is the single place wrappers (catchups, tests) should go through when
+/// reconstructing ledgers from events.
+pub fn build_ledger_meta_with_events(
+    sequence: u32,
+    close_time: u64,
+    events: Vec<ContractEvent>,
+) -> Transition {
+    let mut transition = Transition::new();
+    transition.set_sequence(sequence);
+    transition.set_close_time(close_time);
+
+    for event in events {
+        transition.add_soroban_event(event);
+    }
+
+    transition
+}
+
 #[derive(Debug, Clone)]
 pub struct TransitionPretty {
     pub inner: Transition,
@@ -57,6 +78,19 @@ impl Transition {
         Self { meta }
     }
 
+    /// Loads a transition from a base64-encoded `LedgerCloseMeta`, e.g. one
+    /// captured from a real ledger, so that it can be mutated in tests.
+    pub fn from_base64(meta: &str) -> anyhow::Result<Self> {
+        let meta = LedgerCloseMeta::from_xdr_base64(meta, Limits::none())?;
+        Ok(Self { meta })
+    }
+
+    /// Loads a transition from an XDR-encoded `LedgerCloseMeta`.
+    pub fn from_bytes(meta: &[u8]) -> anyhow::Result<Self> {
+        let meta = LedgerCloseMeta::from_xdr(meta, Limits::none())?;
+        Ok(Self { meta })
+    }
+
     pub fn meta_object(&self) -> LedgerCloseMeta {
         self.meta.clone()
     }
@@ -69,29 +103,57 @@ impl Transition {
         self.meta.to_xdr_base64(Limits::none()).unwrap()
     }
 
-    pub fn set_sequence(&mut self, new_sequence: i64) {
+    pub fn set_sequence(&mut self, new_sequence: u32) {
         match self.meta.clone() {
             LedgerCloseMeta::V1(mut v1) => {
-                v1.ledger_header.header.ledger_seq = new_sequence as u32;
+                v1.ledger_header.header.ledger_seq = new_sequence;
                 self.meta = LedgerCloseMeta::V1(v1)
             }
 
             LedgerCloseMeta::V0(mut v0) => {
-                v0.ledger_header.header.ledger_seq = new_sequence as u32;
+                v0.ledger_header.header.ledger_seq = new_sequence;
                 self.meta = LedgerCloseMeta::V0(v0)
             }
         }
     }
 
-    pub fn set_close_time(&mut self, new_close_time: i64) {
+    pub fn set_close_time(&mut self, new_close_time: u64) {
         match self.meta.clone() {
             LedgerCloseMeta::V1(mut v1) => {
-                v1.ledger_header.header.scp_value.close_time = TimePoint(new_close_time as u64);
+                v1.ledger_header.header.scp_value.close_time = TimePoint(new_close_time);
                 self.meta = LedgerCloseMeta::V1(v1)
             }
 
             LedgerCloseMeta::V0(mut v0) => {
-                v0.ledger_header.header.scp_value.close_time = TimePoint(new_close_time as u64);
+                v0.ledger_header.header.scp_value.close_time = TimePoint(new_close_time);
+                self.meta = LedgerCloseMeta::V0(v0)
+            }
+        }
+    }
+
+    pub fn set_base_fee(&mut self, new_base_fee: u32) {
+        match self.meta.clone() {
+            LedgerCloseMeta::V1(mut v1) => {
+                v1.ledger_header.header.base_fee = new_base_fee;
+                self.meta = LedgerCloseMeta::V1(v1)
+            }
+
+            LedgerCloseMeta::V0(mut v0) => {
+                v0.ledger_header.header.base_fee = new_base_fee;
+                self.meta = LedgerCloseMeta::V0(v0)
+            }
+        }
+    }
+
+    pub fn set_base_reserve(&mut self, new_base_reserve: u32) {
+        match self.meta.clone() {
+            LedgerCloseMeta::V1(mut v1) => {
+                v1.ledger_header.header.base_reserve = new_base_reserve;
+                self.meta = LedgerCloseMeta::V1(v1)
+            }
+
+            LedgerCloseMeta::V0(mut v0) => {
+                v0.ledger_header.header.base_reserve = new_base_reserve;
                 self.meta = LedgerCloseMeta::V0(v0)
             }
         }
@@ -231,7 +293,7 @@ mod tests {
     use stellar_xdr::next::{ContractEvent, Int128Parts, LedgerCloseMeta, Limits, ScSymbol, ScVal};
     use zephyr_sdk::MetaReader;
 
-    use crate::TransitionPretty;
+    use crate::{build_ledger_meta_with_events, Transition, TransitionPretty};
 
     fn to_sdk_xdr_lib<F: stellar_xdr::next::WriteXdr, T: soroban_sdk::xdr::ReadXdr>(xdr: F) -> T {
         T::from_xdr(
@@ -265,6 +327,34 @@ mod tests {
         assert_eq!(20000, metareader.ledger_timestamp())
     }
 
+    #[test]
+    fn change_base_fee_and_reserve() {
+        let mut meta = TransitionPretty::new();
+        meta.inner.set_base_fee(200);
+        meta.inner.set_base_reserve(5000000);
+
+        let header = match meta.inner.meta_object() {
+            LedgerCloseMeta::V0(v0) => v0.ledger_header.header,
+            LedgerCloseMeta::V1(v1) => v1.ledger_header.header,
+        };
+        assert_eq!(200, header.base_fee);
+        assert_eq!(5000000, header.base_reserve);
+    }
+
+    #[test]
+    fn load_from_base64_and_bytes() {
+        let mut transition = Transition::new();
+        transition.set_sequence(20000);
+
+        let loaded = Transition::from_base64(&transition.to_base64()).unwrap();
+        assert_eq!(transition.to_bytes(), loaded.to_bytes());
+
+        let loaded = Transition::from_bytes(&transition.to_bytes()).unwrap();
+        assert_eq!(transition.to_base64(), loaded.to_base64());
+
+        assert!(Transition::from_base64("not a ledger").is_err());
+    }
+
     #[test]
     fn add_event() {
         let mut meta = TransitionPretty::new();
@@ -292,4 +382,34 @@ mod tests {
             metareader.soroban_events()
         );
     }
+
+    #[test]
+    fn build_with_events() {
+        let event = TransitionPretty::new()
+            .contract_event(
+                "CD477X3QMZ76RZORYC6SLMXXRC5OBFGOUAQA7F6NUJMICHJ4DNRKY7ZQ",
+                vec![ScVal::Symbol(ScSymbol("transfer".try_into().unwrap()))],
+                ScVal::I128(Int128Parts {
+                    hi: 0,
+                    lo: 2000000000,
+                }),
+            )
+            .unwrap();
+        let transition = build_ledger_meta_with_events(20000, 30000, vec![event.clone()]);
+
+        let converted = to_sdk_xdr_lib::<LedgerCloseMeta, soroban_sdk::xdr::LedgerCloseMeta>(
+            transition.meta_object(),
+        );
+        let metareader = MetaReader::new(&converted);
+
+        assert_eq!(20000, metareader.ledger_sequence());
+        assert_eq!(30000, metareader.ledger_timestamp());
+        assert_eq!(
+            vec![to_sdk_xdr_lib::<
+                ContractEvent,
+                soroban_sdk::xdr::ContractEvent,
+            >(event)],
+            metareader.soroban_events()
+        );
+    }
 }
@@ -6,7 +6,16 @@ use syn::{
 
 // todo: clean code
 
-#[proc_macro_derive(DatabaseInteract, attributes(with_name))]
+/// Derives `DatabaseInteract` for a struct mapped to the table named by
+/// `#[with_name("...")]`.
+///
+/// Fields of types that implement `ReadXdr`/`WriteXdr` other than `ScVal` and
+/// `Hash` can be stored as XDR rather than bincode with `#[zephyr(xdr)]`.
+///
+/// Rows are read with `DatabaseInteract::try_read_to_rows`, whose errors carry
+/// the index of the column that couldn't be decoded. `read_to_rows`, used by
+/// `EnvClient::read`, panics on the same errors.
+#[proc_macro_derive(DatabaseInteract, attributes(with_name, zephyr))]
 pub fn database_interact_derive(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree
     let input = parse_macro_input!(input as DeriveInput);
@@ -36,7 +45,7 @@ pub fn database_interact_derive(input: TokenStream) -> TokenStream {
         })
         .expect("No with_name attribute");
 
-    let idents: Vec<(Ident, usize, Ident)> = match input.data {
+    let fields = match input.data {
         syn::Data::Struct(s) => match s.fields {
             syn::Fields::Named(FieldsNamed { named, .. }) => named
                 .iter()
@@ -46,18 +55,42 @@ pub fn database_interact_derive(input: TokenStream) -> TokenStream {
                         panic!("unsupported field type")
                     };
 
-                    (
+                    let mut xdr = false;
+                    for attr in field
+                        .attrs
+                        .iter()
+                        .filter(|attr| attr.path().is_ident("zephyr"))
+                    {
+                        attr.parse_nested_meta(|meta| {
+                            if meta.path.is_ident("xdr") {
+                                xdr = true;
+                                Ok(())
+                            } else {
+                                Err(meta.error("unsupported zephyr attribute"))
+                            }
+                        })?;
+                    }
+
+                    Ok((
                         field.ident.clone().unwrap(),
                         idx,
                         path.path.segments[0].ident.clone(),
-                    )
+                        xdr,
+                    ))
                 })
-                .collect(),
+                .collect::<syn::Result<Vec<_>>>(),
             _ => panic!("Unnamed structs are not supported."),
         },
 
         _ => panic!("Unsupported type."),
     };
+
+    // Invalid `zephyr` attributes are reported at their span.
+    let idents: Vec<(Ident, usize, Ident, bool)> = match fields {
+        Ok(idents) => idents,
+        Err(error) => return TokenStream::from(error.to_compile_error()),
+    };
+
     let field_literals: Vec<Lit> = idents
         .iter()
         .map(|ident| {
@@ -66,27 +99,33 @@ pub fn database_interact_derive(input: TokenStream) -> TokenStream {
         })
         .collect();
 
+    let struct_name_str = struct_name.to_string();
+    let field_count = idents.len();
+
     macro_rules! check_type {
         ($t:expr, $($expected:literal),*) => {
             matches!($t, $($expected)|*)
         };
     }
 
-    let construction_code = idents.iter().map(|(ident, _, field_type)| {
-        if check_type!(
-            field_type.to_string().as_str(),
-            "i64",
-            "i128",
-            "u64",
-            "f64",
-            "u32",
-            "i32",
-            "f32",
-            "String",
-            "Vec"
-        ) {
+    let construction_code = idents.iter().map(|(ident, index, field_type, xdr)| {
+        if !xdr
+            && check_type!(
+                field_type.to_string().as_str(),
+                "i64",
+                "i128",
+                "u64",
+                "f64",
+                "u32",
+                "i32",
+                "f32",
+                "String",
+                "Vec"
+            )
+        {
             quote! {
-                #ident: #ident.try_into().unwrap(),
+                #ident: ::core::convert::TryInto::try_into(#ident)
+                    .map_err(|_| (::core::option::Option::Some(#index), ::zephyr_sdk::SdkError::Conversion))?,
             }
         } else {
             quote! {
@@ -95,10 +134,10 @@ pub fn database_interact_derive(input: TokenStream) -> TokenStream {
         }
     });
 
-    let deser_code = idents.iter().map(|(ident, index, field_type)| {
+    let deser_code = idents.iter().map(|(ident, index, field_type, xdr)| {
         let field_string = field_type.to_string();
         let field_str = field_string.as_str();
-        if check_type!(
+        if !xdr && check_type!(
             field_type.to_string().as_str(),
             "i64",
             "i128",
@@ -111,68 +150,82 @@ pub fn database_interact_derive(input: TokenStream) -> TokenStream {
             "Vec"
         ) {
             quote! {
-                let bytes = row.row.get(#index).unwrap();
-                let #ident = bincode::deserialize::<ZephyrVal>(&bytes.0).unwrap();
+                let bytes = row.row.get(#index)
+                    .ok_or((::core::option::Option::Some(#index), ::zephyr_sdk::SdkError::Conversion))?;
+                let #ident = ::zephyr_sdk::bincode::deserialize::<::zephyr_sdk::ZephyrVal>(&bytes.0)
+                    .map_err(|_| (::core::option::Option::Some(#index), ::zephyr_sdk::SdkError::Conversion))?;
 
             }
-        } else if check_type!(field_str, "ScVal", "Hash") {
+        } else if *xdr || check_type!(field_str, "ScVal", "Hash") {
             quote! {
-                let bytes = row.row.get(#index).unwrap();
-                let #ident = ReadXdr::from_xdr(&bytes.0, Limits::none()).unwrap();
+                let bytes = row.row.get(#index)
+                    .ok_or((::core::option::Option::Some(#index), ::zephyr_sdk::SdkError::Conversion))?;
+                let #ident = ::zephyr_sdk::soroban_sdk::xdr::ReadXdr::from_xdr(&bytes.0, ::zephyr_sdk::soroban_sdk::xdr::Limits::none())
+                    .map_err(|_| (::core::option::Option::Some(#index), ::zephyr_sdk::SdkError::Conversion))?;
 
             }
         } else {
             quote! {
-                let bytes = row.row.get(#index).unwrap();
-                let #ident = bincode::deserialize(&bytes.0).unwrap();
+                let bytes = row.row.get(#index)
+                    .ok_or((::core::option::Option::Some(#index), ::zephyr_sdk::SdkError::Conversion))?;
+                let #ident = ::zephyr_sdk::bincode::deserialize(&bytes.0)
+                    .map_err(|_| (::core::option::Option::Some(#index), ::zephyr_sdk::SdkError::Conversion))?;
 
             }
         }
     });
 
-    let serialize_type = idents.iter().map(|(ident, _, field_type)| {
-        if check_type!(field_type.to_string().as_str(), "i64", "i128", "u64", "f64", "u32", "i32", "f32", "String", "Vec") {
+    let serialize_type = idents.iter().map(|(ident, _, field_type, xdr)| {
+        if !xdr && check_type!(field_type.to_string().as_str(), "i64", "i128", "u64", "f64", "u32", "i32", "f32", "String", "Vec") {
             quote! {
-                bincode::serialize(&TryInto::<ZephyrVal>::try_into(self.#ident.clone()).unwrap()).unwrap().as_slice()
+                ::zephyr_sdk::bincode::serialize(&::core::convert::TryInto::<::zephyr_sdk::ZephyrVal>::try_into(self.#ident.clone()).unwrap()).unwrap().as_slice()
             }
-        } else if check_type!(field_type.to_string().as_str(), "ScVal", "Hash") {
+        } else if *xdr || check_type!(field_type.to_string().as_str(), "ScVal", "Hash") {
             quote! {
-                self.#ident.clone().to_xdr(Limits::none()).unwrap().as_slice()
+                ::zephyr_sdk::soroban_sdk::xdr::WriteXdr::to_xdr(&self.#ident, ::zephyr_sdk::soroban_sdk::xdr::Limits::none()).unwrap().as_slice()
             }
         }  else {
             quote! {
-                bincode::serialize(&self.#ident).unwrap().as_slice()
+                ::zephyr_sdk::bincode::serialize(&self.#ident).unwrap().as_slice()
             }
         }
     });
 
-    let serialize_type_update = idents.iter().map(|(ident, _, field_type)| {
-        if check_type!(field_type.to_string().as_str(), "i64", "i128", "u64", "f64", "u32", "i32", "f32", "String", "Vec") {
+    let serialize_type_update = idents.iter().map(|(ident, _, field_type, xdr)| {
+        if !xdr && check_type!(field_type.to_string().as_str(), "i64", "i128", "u64", "f64", "u32", "i32", "f32", "String", "Vec") {
             quote! {
-                bincode::serialize(&TryInto::<ZephyrVal>::try_into(self.#ident.clone()).unwrap()).unwrap().as_slice()
+                ::zephyr_sdk::bincode::serialize(&::core::convert::TryInto::<::zephyr_sdk::ZephyrVal>::try_into(self.#ident.clone()).unwrap()).unwrap().as_slice()
             }
-        } else if check_type!(field_type.to_string().as_str(), "ScVal", "Hash") {
+        } else if *xdr || check_type!(field_type.to_string().as_str(), "ScVal", "Hash") {
             quote! {
-                self.#ident.clone().to_xdr(Limits::none()).unwrap().as_slice()
+                ::zephyr_sdk::soroban_sdk::xdr::WriteXdr::to_xdr(&self.#ident, ::zephyr_sdk::soroban_sdk::xdr::Limits::none()).unwrap().as_slice()
             }
         } else {
             quote! {
-                bincode::serialize(&self.#ident).unwrap().as_slice()
+                ::zephyr_sdk::bincode::serialize(&self.#ident).unwrap().as_slice()
             }
         }
     });
 
-    // Generate the implementation of the trait
+    // Generate the implementation of the trait.
+    // Note: paths are fully qualified so that the derive doesn't depend on the user's imports.
     let expanded = quote! {
-        //use rs_zephyr_sdk::{bincode, ZephyrVal};
-        //use std::convert::TryInto;
-
-        impl DatabaseInteract for #struct_name {
-            fn read_to_rows(env: &EnvClient) -> Vec<Self> where Self: Sized {
-                let rows = env.db_read(&#with_name_attr, &[#(#field_literals),*]).unwrap();
-                let mut result = Vec::new();
+        impl ::zephyr_sdk::DatabaseInteract for #struct_name {
+            /// Reads the rows of the table, returning [`::zephyr_sdk::SdkError::Conversion`]
+            /// when a row doesn't have one column per field or a column doesn't decode
+            /// instead of panicking. Errors carry the index of the column that couldn't
+            /// be read, if any.
+            fn try_read_to_rows(env: &::zephyr_sdk::EnvClient) -> ::core::result::Result<::std::vec::Vec<Self>, (::core::option::Option<usize>, ::zephyr_sdk::SdkError)> where Self: Sized {
+                let rows = env.db_read(&#with_name_attr, &[#(#field_literals),*])
+                    .map_err(|error| (::core::option::Option::None, error))?;
+                let mut result = ::std::vec::Vec::new();
 
                 for row in rows.rows {
+                    // Rows must have exactly one column for each field.
+                    if row.row.len() != #field_count {
+                        return Err((::core::option::Option::None, ::zephyr_sdk::SdkError::Conversion));
+                    }
+
                     #(#deser_code)*
                     result.push(Self {
                         #(#construction_code)*
@@ -180,14 +233,33 @@ pub fn database_interact_derive(input: TokenStream) -> TokenStream {
                 }
 
 
-                result
+                Ok(result)
+            }
+
+            fn read_to_rows(env: &::zephyr_sdk::EnvClient) -> ::std::vec::Vec<Self> where Self: Sized {
+                match <Self as ::zephyr_sdk::DatabaseInteract>::try_read_to_rows(env) {
+                    Ok(rows) => rows,
+                    Err((::core::option::Option::Some(column), error)) => panic!(
+                        "{} column {} of table {} could not be read: {:?}",
+                        #struct_name_str,
+                        column,
+                        #with_name_attr,
+                        error
+                    ),
+                    Err((::core::option::Option::None, error)) => panic!(
+                        "{} rows of table {} could not be read: {:?}",
+                        #struct_name_str,
+                        #with_name_attr,
+                        error
+                    ),
+                }
             }
 
-            fn put(&self, env: &EnvClient) {
+            fn put(&self, env: &::zephyr_sdk::EnvClient) {
                 env.db_write(&#with_name_attr, &[#(#field_literals),*], &[#(#serialize_type),*]).unwrap();
             }
 
-            fn update(&self, env: &EnvClient, conditions: &[Condition]) {
+            fn update(&self, env: &::zephyr_sdk::EnvClient, conditions: &[::zephyr_sdk::Condition]) {
                 env.db_update(&#with_name_attr, &[#(#field_literals),*], &[#(#serialize_type_update),*], conditions).unwrap();
             }
         }
@@ -10,6 +10,7 @@ use anyhow::Result;
 use rs_zephyr_common::DatabaseError;
 
 /// Allowed column conditions
+#[derive(Clone, Debug, PartialEq)]
 pub enum WhereCond {
     /// Where column i64 is equal to the corresponding condition
     /// argument.
@@ -56,6 +57,23 @@ pub trait ZephyrDatabase {
         condition_args: Option<Vec<Vec<u8>>>,
     ) -> Result<Vec<u8>, DatabaseError>;
 
+    /// Reads the latest row from raw data, i.e. the row with the greatest
+    /// value for `order_column` among the rows [`Self::read_raw`] would return.
+    /// The order column is symbol-encoded like the read columns.
+    ///
+    /// Implementations that can't order rows reject the read.
+    fn read_latest_raw(
+        &self,
+        _user_id: i64,
+        _read_point_hash: [u8; 16],
+        _read_data: &[i64],
+        _condition: Option<&[WhereCond]>,
+        _condition_args: Option<Vec<Vec<u8>>>,
+        _order_column: i64,
+    ) -> Result<Vec<u8>, DatabaseError> {
+        Err(DatabaseError::ZephyrQueryError)
+    }
+
     /// Writes the database from raw data.
     /// - user id is the identifier of the host, which might be
     /// needed for database access control depending on how the
@@ -74,6 +92,41 @@ pub trait ZephyrDatabase {
         written: Vec<Vec<u8>>,
     ) -> Result<(), DatabaseError>;
 
+    /// Writes the database from raw data like [`Self::write_raw`], with the
+    /// written row expiring after `ttl_ledgers` ledgers.
+    ///
+    /// Implementations that don't support expiring rows reject the write.
+    fn write_raw_with_ttl(
+        &self,
+        _user_id: i64,
+        _written_point_hash: [u8; 16],
+        _write_data: &[i64],
+        _written: Vec<Vec<u8>>,
+        _ttl_ledgers: u32,
+    ) -> Result<(), DatabaseError> {
+        Err(DatabaseError::WriteError)
+    }
+
+    /// Writes several rows to the same table, where each element of
+    /// `written` holds the values of one row for the columns in `write_data`.
+    ///
+    /// The default implementation writes the rows one by one through
+    /// [`Self::write_raw`], implementations can override it to insert
+    /// them at once.
+    fn write_batch_raw(
+        &self,
+        user_id: i64,
+        written_point_hash: [u8; 16],
+        write_data: &[i64],
+        written: Vec<Vec<Vec<u8>>>,
+    ) -> Result<(), DatabaseError> {
+        for row in written {
+            self.write_raw(user_id, written_point_hash, write_data, row)?;
+        }
+
+        Ok(())
+    }
+
     /// Updates database rows from raw data.
     /// - user id is the identifier of the host, which might be
     /// needed for database access control depending on how the
@@ -84,6 +137,8 @@ pub trait ZephyrDatabase {
     /// about the write operation.
     /// - written is a multidimensional vector with bytes being
     /// written as a single value in the database.
+    ///
+    /// Returns the number of rows affected by the update.
     fn update_raw(
         &self,
         user_id: i64,
@@ -92,7 +147,7 @@ pub trait ZephyrDatabase {
         written: Vec<Vec<u8>>,
         condition: &[WhereCond],
         condition_args: Vec<Vec<u8>>,
-    ) -> Result<(), DatabaseError>;
+    ) -> Result<u64, DatabaseError>;
 }
 
 /// Specify the database permissions that the implementor
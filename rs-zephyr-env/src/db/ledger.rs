@@ -28,7 +28,8 @@ pub trait LedgerStateRead {
         key: ScVal,
     ) -> Option<ContractDataEntry>;
 
-    /// Returns all entries for a contract.
+    /// Returns all entries for a contract. Entries can be returned in any
+    /// order, the host sorts them by key before handing them to the program.
     fn read_contract_data_entries_by_contract_id(
         &self,
         contract: ScAddress,
@@ -1,5 +1,6 @@
 use soroban_env_host::Val;
 use thiserror::Error;
+use wasmi::core::TrapCode;
 
 #[derive(Error, Debug)]
 pub enum InternalError {
@@ -30,24 +31,75 @@ pub enum HostError {
     #[error("Zephyr cannot operate without memory export")]
     NoMemoryExport,
 
+    #[error("Host function {0} requires a guest compiled with multi-value")]
+    MultiValueRequired(String),
+
     #[error("Tried reading stack at an index where no value is on it")]
     NoValOnStack,
 
+    #[error("Stack instructions sections are not in the expected order")]
+    MalformedInstructions,
+
     #[error("Tried overwriting ledger close meta")]
     LedgerCloseMetaOverridden,
 
     #[error("Requested ledger close meta but it is none")]
     NoLedgerCloseMeta,
 
+    #[error("No ledger found in the ledger database")]
+    NoCurrentLedger,
+
+    #[error("Ledger sequence {0} is out of the u32 range")]
+    InvalidLedgerSequence(i64),
+
+    #[error("Ledger close time {0} is negative")]
+    InvalidCloseTime(i64),
+
+    #[error("Row ttl of {0} ledgers is out of the u32 range")]
+    InvalidTtl(i64),
+
     #[error("Requested ledger entry doesn't exist")]
     NoLedgerEntry,
 
+    #[error("Requested checkpoint but none was written")]
+    NoCheckpoint,
+
+    #[error("Program is not allowed to access the table")]
+    TableNotAllowed,
+
+    #[error("Invalid table name {0}")]
+    InvalidTableName(String),
+
+    #[error("Requested configuration value but none was set")]
+    NoConfigValue,
+
     #[error("Invalid types found on function result")]
     InvalidFunctionResult,
 
+    #[error("Program ran out of fuel")]
+    OutOfFuel,
+
+    #[error("Program trapped: {0}")]
+    Trap(TrapCode),
+
     #[error("Tried using the transmitter but didn't provide one")]
     NoTransmitter,
 
+    #[error("Program exceeded the maximum number of relayed HTTP requests")]
+    HttpQuotaExceeded,
+
+    #[error("Program exceeded the maximum number of host function calls")]
+    HostCallQuotaExceeded,
+
+    #[error("Program result exceeds the maximum of {0} bytes")]
+    ResultTooLarge(usize),
+
+    #[error("Public key is not a valid ed25519 key")]
+    InvalidEd25519PublicKey,
+
+    #[error("Signature is not a valid ed25519 signature")]
+    InvalidEd25519Signature,
+
     #[error("Internal Error")]
     InternalError(InternalError),
 
@@ -56,4 +108,36 @@ pub enum HostError {
 
     #[error("Error on the Soroban host side: {0:?}")]
     SorobanHostWithContext(soroban_env_host::Error),
+
+    #[error("Soroban host initialization failed while {0}: {1:?}")]
+    SorobanInit(&'static str, soroban_env_host::Error),
+
+    #[error("Program exceeded the Soroban budget")]
+    SorobanBudgetExceeded,
+}
+
+#[derive(Error, Debug)]
+pub enum RelayError {
+    #[error("Relayed message could not be serialized: {0}")]
+    Serialization(bincode::Error),
+
+    #[error("Relayed message could not be deserialized: {0}")]
+    Deserialization(bincode::Error),
+
+    #[error("Relayed message has unsupported wire format version {0}")]
+    UnsupportedVersion(u8),
+}
+
+/// Failure of an HTTP request relayed by a program in the test utilities.
+#[cfg(feature = "testutils")]
+#[derive(Error, Debug)]
+pub enum HttpRelayError {
+    #[error("Relayed request has an invalid header {0:?}")]
+    InvalidHeader(String),
+
+    #[error("Relayed request failed: {0}")]
+    Request(reqwest::Error),
+
+    #[error("Relayed request task failed: {0}")]
+    Task(tokio::task::JoinError),
 }
@@ -3,10 +3,9 @@
 //! between the binary code executed within the VM and
 //! the implementor.
 
-use crate::error::InternalError;
 use crate::snapshot::snapshot_utils;
 use crate::soroban_host_gen::{self, build_u32val, with_frame, RelativeObjectConversion};
-use crate::trace::{StackTrace, TracePoint};
+use crate::trace::{Redaction, StackTrace, TracePoint};
 use crate::{
     budget::Budget,
     db::{
@@ -14,6 +13,7 @@ use crate::{
         ledger::{Ledger, LedgerStateRead},
     },
     error::HostError,
+    relay,
     stack::Stack,
     vm::Vm,
     vm_context::VmContext,
@@ -21,20 +21,22 @@ use crate::{
 };
 use anyhow::Result;
 use memory::CustomVMCtx;
-use rs_zephyr_common::{wrapping::WrappedMaxBytes, ZephyrStatus};
+use rs_zephyr_common::{wrapping::WrappedMaxBytes, RelayedMessageRequest, ZephyrStatus};
 use soroban_env_host::budget::AsBudget;
-use soroban_env_host::xdr::{Hash, Limits, ReadXdr, ScAddress, ScVal};
+use soroban_env_host::xdr::{Hash, Limits, ReadXdr, ScAddress, ScErrorCode, ScErrorType, ScVal};
 use soroban_env_host::{wasmi as soroban_wasmi, BytesObject, Env, I128Object, VecObject, VmCaller};
 use soroban_env_host::{CheckedEnvArg, MapObject, Symbol, Val};
 use std::{
     borrow::BorrowMut,
     cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
     rc::{Rc, Weak},
 };
 use tokio::sync::mpsc::UnboundedSender;
 use utils::soroban::ZephyrTestContract;
-use wasmi::{Caller, Func, Store, Val as Value};
+use wasmi::{Caller, Func, IntoFunc, Linker, Store, Val as Value};
 
+pub(crate) mod crypto;
 pub(crate) mod database;
 pub(crate) mod memory;
 pub(crate) mod soroban;
@@ -42,6 +44,13 @@ pub(crate) mod utils;
 
 type ZephyrRelayer = UnboundedSender<Vec<u8>>;
 
+/// Id of the sample contract registered in the Soroban host. Actions performed
+/// by the ZephyrVM through the Soroban host are executed in the frame of this contract.
+pub(crate) const ZEPHYR_CONTRACT_ID: [u8; 32] = [0; 32];
+
+/// Default maximum size in bytes of a result streamed through `conclude_chunk`.
+pub const DEFAULT_MAX_RESULT_BYTES: usize = 64 * 1024 * 1024;
+
 /// Information about the entry point function. This
 /// function is exported by the binary with the given
 /// argument types.
@@ -82,6 +91,33 @@ impl ZephyrStandard for InvokedFunctionInfo {
     }
 }
 
+/// Kind of the input bytes loaded in the host, so that the guest knows
+/// how to decode them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputKind {
+    /// XDR-encoded ledger close meta, for ingestion programs.
+    LedgerCloseMeta = 0,
+
+    /// Bincode-serialized function arguments, for serverless functions.
+    FunctionArgs = 1,
+}
+
+/// Maps errors of the Soroban host setup to [`HostError::SorobanInit`],
+/// recording the step that failed.
+fn soroban_init_error(step: &'static str) -> impl Fn(soroban_env_host::HostError) -> HostError {
+    move |error| HostError::SorobanInit(step, error.error)
+}
+
+/// Maps errors of the Soroban host functions, telling apart the exhaustion of
+/// the budget set with [`Host::set_soroban_budget`].
+pub(crate) fn soroban_error(error: soroban_env_host::Error) -> HostError {
+    if error.is_type(ScErrorType::Budget) && error.is_code(ScErrorCode::ExceededLimit) {
+        HostError::SorobanBudgetExceeded
+    } else {
+        HostError::SorobanHostWithContext(error)
+    }
+}
+
 /// Zephyr Host State Implementation.
 #[derive(Clone)]
 pub struct HostImpl<DB: ZephyrDatabase, L: LedgerStateRead> {
@@ -94,9 +130,63 @@ pub struct HostImpl<DB: ZephyrDatabase, L: LedgerStateRead> {
     /// Transmitter
     pub transmitter: RefCell<Option<ZephyrRelayer>>,
 
+    /// Maximum number of HTTP requests a single invocation can relay.
+    /// No limit is enforced when none.
+    pub max_http_requests: RefCell<Option<u32>>,
+
+    /// Number of HTTP requests relayed during the current invocation.
+    pub http_requests: RefCell<u32>,
+
+    /// Maximum number of host functions calls a single invocation can
+    /// perform. No limit is enforced when none.
+    pub max_host_calls: RefCell<Option<u64>>,
+
+    /// Number of host function calls performed during the current invocation.
+    pub host_calls: RefCell<u64>,
+
+    /// Whether identical consecutive logs are collapsed into a summary.
+    pub dedup_logs: RefCell<bool>,
+
+    /// Last relayed log and the number of times it was repeated since.
+    pub last_log: RefCell<Option<(Vec<u8>, u32)>>,
+
     /// Result of the invocation. Currently this can only be a string.
     pub result: RefCell<String>,
 
+    /// Binary result of the invocation, written by programs that
+    /// return raw bytes instead of a string.
+    pub binary_result: RefCell<Option<Vec<u8>>>,
+
+    /// Chunks of a binary result that is still being streamed by the
+    /// program through `conclude_chunk`.
+    pub pending_chunks: RefCell<Vec<u8>>,
+
+    /// Maximum size in bytes of a result streamed through `conclude_chunk`.
+    pub max_result_bytes: RefCell<usize>,
+
+    /// Number of rows affected by the latest database update.
+    pub affected_rows: RefCell<u64>,
+
+    /// Latest checkpoint written by the program. Unlike the result, the
+    /// checkpoint is retained by the host even when the invocation traps
+    /// so that the program can resume from it when invoked again.
+    pub checkpoint: RefCell<Option<Vec<u8>>>,
+
+    /// Operator-provided configuration values the program can read.
+    pub config: RefCell<HashMap<String, String>>,
+
+    /// Tables the program is allowed to access, as symbol-encoded names.
+    /// All of the host's tables are accessible when none.
+    pub allowed_tables: RefCell<Option<Vec<i64>>>,
+
+    /// CPU instructions and memory bytes the Soroban host can use for each
+    /// call through it. The Soroban budget is unlimited when none.
+    pub soroban_budget: RefCell<Option<(u64, u64)>>,
+
+    /// Whether the program is being invoked to reprocess historical
+    /// ledgers rather than for live ingestion.
+    pub catchup: RefCell<bool>,
+
     /// Latest ledger close meta. This is set as optional as
     /// some Zephyr programs might not need the ledger meta.
     ///
@@ -107,6 +197,9 @@ pub struct HostImpl<DB: ZephyrDatabase, L: LedgerStateRead> {
     /// - a request body < for functions
     pub latest_close: RefCell<Option<Vec<u8>>>, // some zephyr programs might not need the ledger close meta
 
+    /// Kind of the input loaded in the latest close slot.
+    pub input_kind: RefCell<Option<InputKind>>,
+
     /// Database implementation.
     pub database: RefCell<Database<DB>>,
 
@@ -134,7 +227,7 @@ pub struct HostImpl<DB: ZephyrDatabase, L: LedgerStateRead> {
 
 /// Zephyr Host State.
 #[derive(Clone)]
-pub struct Host<DB: ZephyrDatabase, L: LedgerStateRead>(Rc<HostImpl<DB, L>>); // We wrap [`HostImpl`] here inside an rc pointer for multi ownership.
+pub struct Host<DB: ZephyrDatabase, L: LedgerStateRead>(pub(crate) Rc<HostImpl<DB, L>>); // We wrap [`HostImpl`] here inside an rc pointer for multi ownership.
 
 // Tracing-friendly utils implementations
 impl<DB: ZephyrDatabase, L: LedgerStateRead> Host<DB, L> {
@@ -152,34 +245,72 @@ impl<DB: ZephyrDatabase + ZephyrStandard, L: LedgerStateRead + ZephyrStandard> H
     /// and the entity it is bound to. For instance, in Mercury
     /// the host id is the id of a Mercury user. This is needed to
     /// implement role constraints in Zephyr.
+    ///
+    /// The Soroban ledger context is initialized with the latest ledger
+    /// found in the ledger database, errors if there is none.
     pub fn from_id(id: i64, network_id: [u8; 32]) -> Result<Self> {
+        let (sequence, timestamp) = snapshot_utils::get_current_ledger_sequence()?;
+
+        Self::from_id_with_ledger(id, network_id, sequence, timestamp)
+    }
+
+    /// Creates a standard Host object like [`Self::from_id`] but with an
+    /// explicit ledger sequence and close time for the Soroban ledger context,
+    /// without reading the ledger database.
+    pub fn from_id_with_ledger(
+        id: i64,
+        network_id: [u8; 32],
+        sequence: u32,
+        timestamp: u64,
+    ) -> Result<Self> {
         let host = soroban_env_host::Host::test_host_with_recording_footprint();
-        host.as_budget().reset_unlimited().unwrap();
+        host.as_budget()
+            .reset_unlimited()
+            .map_err(soroban_init_error("resetting the budget"))?;
         host.with_mut_ledger_info(|li| {
-            let (sequence, timestamp) = snapshot_utils::get_current_ledger_sequence();
-            li.sequence_number = sequence as u32;
-            li.timestamp = timestamp as u64;
+            li.sequence_number = sequence;
+            li.timestamp = timestamp;
             li.network_id = network_id;
 
             li.protocol_version = 21;
-        })?;
-        host.enable_debug()?;
+        })
+        .map_err(soroban_init_error("setting the ledger info"))?;
+        host.enable_debug()
+            .map_err(soroban_init_error("enabling debug mode"))?;
 
         let test_contract = Rc::new(ZephyrTestContract::new());
-        let contract_id_bytes = [0; 32];
-        let contract_address = ScAddress::Contract(Hash(contract_id_bytes));
-        let contract_id = host.add_host_object(contract_address)?;
+        let contract_address = ScAddress::Contract(Hash(ZEPHYR_CONTRACT_ID));
+        let contract_id = host
+            .add_host_object(contract_address)
+            .map_err(soroban_init_error("adding the contract address"))?;
 
         // Since Soroban's Host relies on a contract to give context to the execution actions
         // performed in the ZephyrVM are connected to a non-existing sample contract address.
-        host.register_test_contract(contract_id, test_contract)?;
+        host.register_test_contract(contract_id, test_contract)
+            .map_err(soroban_init_error("registering the test contract"))?;
 
         Ok(Self(Rc::new(HostImpl {
             id,
             network_id,
             transmitter: RefCell::new(None),
+            max_http_requests: RefCell::new(None),
+            http_requests: RefCell::new(0),
+            max_host_calls: RefCell::new(None),
+            host_calls: RefCell::new(0),
+            dedup_logs: RefCell::new(false),
+            last_log: RefCell::new(None),
             result: RefCell::new(String::new()),
+            binary_result: RefCell::new(None),
+            pending_chunks: RefCell::new(Vec::new()),
+            max_result_bytes: RefCell::new(DEFAULT_MAX_RESULT_BYTES),
+            affected_rows: RefCell::new(0),
+            checkpoint: RefCell::new(None),
+            config: RefCell::new(HashMap::new()),
+            allowed_tables: RefCell::new(None),
+            soroban_budget: RefCell::new(None),
+            catchup: RefCell::new(false),
             latest_close: RefCell::new(None),
+            input_kind: RefCell::new(None),
             database: RefCell::new(Database::zephyr_standard()?),
             ledger: Ledger::zephyr_standard()?,
             budget: RefCell::new(Budget::zephyr_standard()?),
@@ -197,14 +328,18 @@ impl<DB: ZephyrDatabase + ZephyrMock, L: LedgerStateRead + ZephyrMock> ZephyrMoc
     /// mocked data such as host id, databases and context.
     fn mocked() -> Result<Self> {
         let host = soroban_env_host::Host::test_host_with_recording_footprint();
-        host.as_budget().reset_unlimited().unwrap();
+        host.as_budget()
+            .reset_unlimited()
+            .map_err(soroban_init_error("resetting the budget"))?;
         host.with_mut_ledger_info(|li| {
             li.protocol_version = 21;
-        })?;
+        })
+        .map_err(soroban_init_error("setting the ledger info"))?;
         let test_contract = Rc::new(ZephyrTestContract {});
-        let contract_id_bytes = [0; 32];
-        let contract_address = ScAddress::Contract(Hash(contract_id_bytes));
-        let contract_id = host.add_host_object(contract_address)?;
+        let contract_address = ScAddress::Contract(Hash(ZEPHYR_CONTRACT_ID));
+        let contract_id = host
+            .add_host_object(contract_address)
+            .map_err(soroban_init_error("adding the contract address"))?;
 
         // Since Soroban's Host relies on a contract to give context to the execution actions
         // performed in the ZephyrVM are connected to a non-existing sample contract address.
@@ -214,8 +349,24 @@ impl<DB: ZephyrDatabase + ZephyrMock, L: LedgerStateRead + ZephyrMock> ZephyrMoc
             id: 0,
             network_id: [0; 32],
             transmitter: RefCell::new(None),
+            max_http_requests: RefCell::new(None),
+            http_requests: RefCell::new(0),
+            max_host_calls: RefCell::new(None),
+            host_calls: RefCell::new(0),
+            dedup_logs: RefCell::new(false),
+            last_log: RefCell::new(None),
             result: RefCell::new(String::new()),
+            binary_result: RefCell::new(None),
+            pending_chunks: RefCell::new(Vec::new()),
+            max_result_bytes: RefCell::new(DEFAULT_MAX_RESULT_BYTES),
+            affected_rows: RefCell::new(0),
+            checkpoint: RefCell::new(None),
+            config: RefCell::new(HashMap::new()),
+            allowed_tables: RefCell::new(None),
+            soroban_budget: RefCell::new(None),
+            catchup: RefCell::new(false),
             latest_close: RefCell::new(None),
+            input_kind: RefCell::new(None),
             database: RefCell::new(Database::mocked()?),
             ledger: Ledger::mocked()?,
             budget: RefCell::new(Budget::zephyr_standard()?),
@@ -232,33 +383,51 @@ impl<DB: ZephyrDatabase + ZephyrMock, L: LedgerStateRead + ZephyrMock> ZephyrMoc
 /// This object is sent to the VM object when the Virtual Machine
 /// is created to tell the linker which host functions to define.
 #[derive(Clone)]
-pub struct FunctionInfo {
+pub struct FunctionInfo<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> {
     /// Module name.
     pub module: &'static str,
 
     /// Function name.
     pub func: &'static str,
 
-    /// Func object. Contains the function's implementation.
-    pub wrapped: Func,
+    /// Host function. Contains the function's implementation.
+    pub wrapped: HostFunc<Host<DB, L>>,
 }
 
-/// Wrapper function information.
-/// This object is sent to the VM object when the Virtual Machine
-/// is created to tell the linker which host functions to define.
+/// Implementation of a host function that isn't bound to any store, so that
+/// it can be created once and defined on the linker of every VM.
 #[derive(Clone)]
-pub struct SorobanTempFunctionInfo<
-    DB: ZephyrDatabase + Clone + 'static,
-    L: LedgerStateRead + 'static,
-> {
-    /// Module name.
-    pub module: &'static str,
+pub struct HostFunc<T> {
+    define: Rc<dyn Fn(&mut Linker<T>, &str, &str) -> Result<()>>,
+    wrap: Rc<dyn Fn(&mut Store<T>) -> Func>,
+}
 
-    /// Function name.
-    pub func: &'static str,
+impl<T: 'static> HostFunc<T> {
+    /// Creates a host function from its implementation.
+    pub fn new<Params: 'static, Results: 'static>(
+        func: impl IntoFunc<T, Params, Results> + Clone,
+    ) -> Self {
+        let defined = func.clone();
 
-    /// Func object. Contains the function's implementation.
-    pub wrapped: fn(&mut Store<Host<DB, L>>) -> Func,
+        Self {
+            define: Rc::new(move |linker: &mut Linker<T>, module: &str, name: &str| {
+                linker.func_wrap(module, name, defined.clone())?;
+                Ok(())
+            }),
+            wrap: Rc::new(move |store: &mut Store<T>| Func::wrap(store, func.clone())),
+        }
+    }
+
+    /// Defines the function on the linker. Errors when the linker already
+    /// defines a function with the same name.
+    pub fn define(&self, linker: &mut Linker<T>, module: &str, name: &str) -> Result<()> {
+        (self.define)(linker, module, name)
+    }
+
+    /// Wraps the function into the given store.
+    pub fn wrap(&self, store: &mut Store<T>) -> Func {
+        (self.wrap)(store)
+    }
 }
 
 #[allow(dead_code)]
@@ -277,12 +446,31 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             "Adding ledger close meta to ZVM.",
             false,
         );
+        self.add_input(ledger_close_meta, InputKind::LedgerCloseMeta)
+    }
+
+    /// Loads the serialized arguments of a serverless function invocation.
+    ///
+    /// Function arguments share the input slot with the ledger close meta, but
+    /// are tagged as [`InputKind::FunctionArgs`] so that the guest doesn't try
+    /// to decode them as XDR.
+    pub fn add_function_input(&mut self, input: Vec<u8>) -> Result<()> {
+        self.0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            "Adding function input to ZVM.",
+            false,
+        );
+        self.add_input(input, InputKind::FunctionArgs)
+    }
+
+    fn add_input(&mut self, input: Vec<u8>, kind: InputKind) -> Result<()> {
         let current = &self.0.latest_close;
         if current.borrow().is_some() {
             return Err(HostError::LedgerCloseMetaOverridden.into());
         }
 
-        *current.borrow_mut() = Some(ledger_close_meta);
+        *current.borrow_mut() = Some(input);
+        *self.0.input_kind.borrow_mut() = Some(kind);
 
         Ok(())
     }
@@ -296,6 +484,22 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         }
     }
 
+    /// Restricts the stack trace to the given trace point categories, e.g.
+    /// to only trace [`TracePoint::DatabaseImpl`] while debugging database
+    /// operations. All categories are traced by default.
+    pub fn set_trace_points(&mut self, points: &[TracePoint]) {
+        self.0
+            .stack_trace
+            .borrow_mut()
+            .set_trace_points(points.iter().copied());
+    }
+
+    /// Sets the redaction applied to the program data included in traces and
+    /// to the data of logs relayed by the program. Disabled by default.
+    pub fn set_redaction(&mut self, redaction: Redaction) {
+        self.0.stack_trace.borrow_mut().set_redaction(redaction);
+    }
+
     /// Adds a transmitter that will be used to send message to the
     /// associated receiver once every time the [`Self::send_message`]
     /// host is called.
@@ -307,16 +511,219 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         *current.borrow_mut() = Some(transmitter);
     }
 
+    /// Collapses identical consecutive logs relayed by the program into a
+    /// single "repeated N times" log. Disabled by default.
+    pub fn set_log_dedup(&mut self, active: bool) {
+        *self.0.dedup_logs.borrow_mut() = active;
+    }
+
+    /// Relays the summary of the last log if it was repeated. Called once
+    /// the invocation is over since there is no following message to
+    /// trigger it.
+    pub fn flush_repeated_logs(&self) -> Result<()> {
+        let last = self.0.last_log.borrow_mut().take();
+
+        if let Some(summary) = Self::repeated_log_summary(last)? {
+            if let Some(tx) = self.0.transmitter.borrow().as_ref() {
+                tx.send(summary)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Marks the invocation as part of a catchup, i.e. the program is
+    /// reprocessing historical ledgers rather than ingesting live ones.
+    pub fn set_catchup_mode(&mut self, catchup: bool) {
+        *self.0.catchup.borrow_mut() = catchup;
+    }
+
+    /// Returns whether the invocation is part of a catchup.
+    pub fn is_catchup(&self) -> bool {
+        *self.0.catchup.borrow()
+    }
+
+    /// Caps the number of HTTP requests the program can relay through
+    /// [`Self::send_message`] during each invocation. Requests beyond the
+    /// limit are rejected with [`HostError::HttpQuotaExceeded`].
+    pub fn set_max_http_requests(&mut self, max: u32) {
+        *self.0.max_http_requests.borrow_mut() = Some(max);
+    }
+
+    /// Caps the number of host functions the program can call during an
+    /// invocation, independently of the fuel it consumes. The call exceeding
+    /// the limit traps and the invocation fails with [`HostError::HostCallQuotaExceeded`].
+    ///
+    /// Host functions are only counted when the cap is set before the VM is
+    /// created, so that uncapped programs don't pay for the counting.
+    pub fn set_max_host_calls(&mut self, max: u64) {
+        *self.0.max_host_calls.borrow_mut() = Some(max);
+    }
+
+    /// Returns the number of host functions called by the program during the
+    /// current invocation. Always zero unless a cap is set, see [`Self::set_max_host_calls`].
+    pub fn host_calls(&self) -> u64 {
+        *self.0.host_calls.borrow()
+    }
+
+    /// Whether the calls to host functions are counted.
+    pub(crate) fn counts_host_calls(&self) -> bool {
+        self.0.max_host_calls.borrow().is_some()
+    }
+
+    /// Caps the size of the result the program can stream through
+    /// `conclude_chunk`, [`DEFAULT_MAX_RESULT_BYTES`] by default. Chunks
+    /// exceeding the limit are rejected with [`HostError::ResultTooLarge`].
+    pub fn set_max_result_bytes(&mut self, max: usize) {
+        *self.0.max_result_bytes.borrow_mut() = max;
+    }
+
+    /// Resets the per-invocation state before the program is invoked, so that
+    /// an invocation never observes what a previous (possibly trapped) one left.
+    pub(crate) fn reset_invocation_state(&self) -> Result<()> {
+        *self.0.host_calls.borrow_mut() = 0;
+        *self.0.http_requests.borrow_mut() = 0;
+        self.clear_result();
+        *self.0.affected_rows.borrow_mut() = 0;
+
+        self.reset_soroban_budget()
+    }
+
+    /// Whether the program called more host functions than allowed by
+    /// [`Self::set_max_host_calls`].
+    pub(crate) fn host_call_quota_exceeded(&self) -> bool {
+        self.0
+            .max_host_calls
+            .borrow()
+            .is_some_and(|max| *self.0.host_calls.borrow() > max)
+    }
+
+    fn count_host_call(&self) -> Result<()> {
+        let mut calls = self.0.host_calls.borrow_mut();
+        *calls += 1;
+
+        if let Some(max) = *self.0.max_host_calls.borrow() {
+            if *calls > max {
+                self.0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::ZephyrEnvironment,
+                    format!("Program exceeded the quota of {} host function calls.", max),
+                    true,
+                );
+                return Err(HostError::HostCallQuotaExceeded.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Wraps a host function so that its calls are counted, see
+    /// [`Self::set_max_host_calls`]. Exceeding the quota traps the program.
+    pub(crate) fn counted_function(store: &mut Store<Self>, func: Func) -> Func {
+        let ty = func.ty(&*store);
+
+        Func::new(
+            store,
+            ty,
+            move |mut caller: Caller<Self>, params: &[Value], results: &mut [Value]| {
+                caller
+                    .data()
+                    .count_host_call()
+                    .map_err(|error| wasmi::Error::new(error.to_string()))?;
+
+                func.call(&mut caller, params, results)
+            },
+        )
+    }
+
+    /// Restricts the program to the given tables. Database operations on
+    /// other tables fail with [`HostError::TableNotAllowed`].
+    pub fn set_allowed_tables(&mut self, tables: &[&str]) -> Result<()> {
+        let mut allowed = Vec::new();
+        for table in tables {
+            let symbol = Symbol::try_from_small_str(table)
+                .map_err(|_| HostError::InvalidTableName(table.to_string()))?;
+            allowed.push(Val::from(symbol).get_payload() as i64);
+        }
+
+        *self.0.allowed_tables.borrow_mut() = Some(allowed);
+
+        Ok(())
+    }
+
+    /// Bounds the budget of the Soroban host so that the cost of the Soroban
+    /// host functions invoked by the program is metered too. The limits apply
+    /// to each invocation as a whole: all the conversions the program makes
+    /// draw down the same budget, and an invocation exhausting it fails with
+    /// [`HostError::SorobanBudgetExceeded`]. By default the Soroban budget is
+    /// unlimited and only the VM's fuel bounds execution.
+    pub fn set_soroban_budget(&mut self, cpu_insns: u64, mem_bytes: u64) -> Result<()> {
+        *self.0.soroban_budget.borrow_mut() = Some((cpu_insns, mem_bytes));
+        self.reset_soroban_budget()
+    }
+
+    /// Resets the budget of the Soroban host, applying the limits set with
+    /// [`Self::set_soroban_budget`] if any.
+    fn reset_soroban_budget(&self) -> Result<()> {
+        let soroban = self.0.soroban.borrow();
+        let budget = soroban.as_budget();
+        if let Some((cpu_insns, mem_bytes)) = *self.0.soroban_budget.borrow() {
+            budget
+                .reset_limits(cpu_insns, mem_bytes)
+                .map_err(soroban_init_error("limiting the budget"))?;
+        } else {
+            budget
+                .reset_unlimited()
+                .map_err(soroban_init_error("resetting the budget"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the program consumed more than the Soroban budget set with
+    /// [`Self::set_soroban_budget`] during the current invocation.
+    pub(crate) fn soroban_budget_exceeded(&self) -> bool {
+        let Some((cpu_insns, mem_bytes)) = *self.0.soroban_budget.borrow() else {
+            return false;
+        };
+
+        let soroban = self.0.soroban.borrow();
+        let budget = soroban.as_budget();
+
+        budget
+            .get_cpu_insns_consumed()
+            .is_ok_and(|consumed| consumed > cpu_insns)
+            || budget
+                .get_mem_bytes_consumed()
+                .is_ok_and(|consumed| consumed > mem_bytes)
+    }
+
+    /// Returns a reference to the host's database implementation.
+    pub fn as_database(&self) -> Ref<Database<DB>> {
+        self.0.database.borrow()
+    }
+
     /// Returns a reference to the host's budget implementation.
     pub fn as_budget(&self) -> Ref<Budget> {
         self.0.budget.borrow()
     }
 
+    /// Returns a mutable reference to the host's stack trace.
+    pub fn as_stack_trace_mut(&self) -> RefMut<StackTrace> {
+        self.0.stack_trace.borrow_mut()
+    }
+
     /// Returns the id assigned to the host.
     pub fn get_host_id(&self) -> i64 {
         self.0.id
     }
 
+    /// Returns the address of the contract registered in the Soroban host.
+    /// Soroban actions performed by the guest (for instance simulations)
+    /// are executed as this contract.
+    pub fn contract_address(&self) -> ScAddress {
+        ScAddress::Contract(Hash(ZEPHYR_CONTRACT_ID))
+    }
+
     /// Returns a reference to the host's entry point information.
     pub fn get_entry_point_info(&self) -> Ref<InvokedFunctionInfo> {
         self.0.entry_point_info.borrow()
@@ -369,6 +776,29 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         Self::write_to_memory(caller, ledger_close_meta).1
     }
 
+    fn current_ledger_sequence(caller: &Caller<Self>) -> Result<u32> {
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::LedgerImpl,
+            "Reading the current ledger sequence from the snapshot.",
+            false,
+        );
+        let (sequence, _) = snapshot_utils::get_current_ledger_sequence()?;
+
+        Ok(sequence)
+    }
+
+    fn read_input_kind(caller: &Caller<Self>) -> Result<InputKind> {
+        let kind = *caller.data().0.input_kind.borrow();
+
+        Ok(kind.ok_or_else(|| HostError::NoLedgerCloseMeta)?)
+    }
+
+    fn memory_pages(caller: &Caller<Self>) -> Result<u32> {
+        let memory = Self::guest_memory(caller)?;
+
+        Ok(memory.current_pages(caller).into())
+    }
+
     /// Sends a message to any receiver whose sender has been provided to the
     /// host object.
     pub fn send_message(caller: Caller<Self>, offset: i64, size: i64) -> Result<()> {
@@ -380,23 +810,27 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         let host = caller.data();
 
         let message = {
-            let memory = {
-                let context = host.0.context.borrow();
-                let vm = context
-                    .vm
-                    .as_ref()
-                    .ok_or_else(|| HostError::NoContext)?
-                    .upgrade()
-                    .ok_or_else(|| HostError::InternalError(InternalError::CannotUpgradeRc))?;
-                let mem_manager = &vm.memory_manager;
-
-                mem_manager.memory
-            };
+            let memory = Self::guest_memory(&caller)?;
 
             let segment = (offset, size);
             Self::read_segment_from_memory(&memory, &caller, segment)?
         };
 
+        let request = relay::decode_message(&message).ok();
+        let is_http = matches!(request, Some(RelayedMessageRequest::Http(_)));
+        if is_http {
+            if let Some(max) = *host.0.max_http_requests.borrow() {
+                if *host.0.http_requests.borrow() >= max {
+                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                        TracePoint::ZephyrEnvironment,
+                        format!("Program exceeded the quota of {} HTTP requests.", max),
+                        true,
+                    );
+                    return Err(HostError::HttpQuotaExceeded.into());
+                }
+            }
+        }
+
         caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
             TracePoint::ZephyrEnvironment,
             "Successfully read user message, sending to transmitter.",
@@ -415,11 +849,95 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             return Err(HostError::NoTransmitter.into());
         };
 
-        tx.send(message)?;
+        let is_log = matches!(request, Some(RelayedMessageRequest::Log(_)));
+        let message = Self::redact_log(&caller, message, request)?;
+        for message in host.dedup_message(message, is_log)? {
+            tx.send(message)?;
+        }
+
+        // Only requests that were actually sent count towards the quota.
+        if is_http {
+            *host.0.http_requests.borrow_mut() += 1;
+        }
 
         Ok(())
     }
 
+    /// Redacts the data of relayed logs according to the configured
+    /// [`Redaction`]. Other messages are relayed as is.
+    fn redact_log(
+        caller: &Caller<Self>,
+        message: Vec<u8>,
+        request: Option<RelayedMessageRequest>,
+    ) -> Result<Vec<u8>> {
+        let redaction = caller.data().0.stack_trace.borrow().redaction();
+        if redaction == Redaction::Disabled {
+            return Ok(message);
+        }
+
+        match request {
+            Some(RelayedMessageRequest::Log(mut log)) => {
+                log.data = log.data.map(|data| redaction.redact_bytes(data));
+
+                Ok(relay::encode_message_like(
+                    &message,
+                    &RelayedMessageRequest::Log(log),
+                )?)
+            }
+            _ => Ok(message),
+        }
+    }
+
+    /// Returns the messages to relay for the given message. When log dedup is
+    /// enabled, a log identical to the previous one is swallowed, and the
+    /// summary of a repeated log is relayed before the next message.
+    fn dedup_message(&self, message: Vec<u8>, is_log: bool) -> Result<Vec<Vec<u8>>> {
+        if !*self.0.dedup_logs.borrow() {
+            return Ok(vec![message]);
+        }
+
+        let mut last = self.0.last_log.borrow_mut();
+
+        if is_log {
+            if let Some((last_message, repeats)) = last.as_mut() {
+                if *last_message == message {
+                    *repeats += 1;
+                    return Ok(vec![]);
+                }
+            }
+        }
+
+        let mut messages = Vec::new();
+        if let Some(summary) = Self::repeated_log_summary(last.take())? {
+            messages.push(summary);
+        }
+
+        if is_log {
+            *last = Some((message.clone(), 0));
+        }
+        messages.push(message);
+
+        Ok(messages)
+    }
+
+    fn repeated_log_summary(last: Option<(Vec<u8>, u32)>) -> Result<Option<Vec<u8>>> {
+        let (message, repeats) = match last {
+            Some((message, repeats)) if repeats > 0 => (message, repeats),
+            _ => return Ok(None),
+        };
+
+        if let Ok(RelayedMessageRequest::Log(mut log)) = relay::decode_message(&message) {
+            log.message = format!("{} (repeated {} times)", log.message, repeats);
+
+            return Ok(Some(relay::encode_message_like(
+                &message,
+                &RelayedMessageRequest::Log(log),
+            )?));
+        }
+
+        Ok(None)
+    }
+
     fn write_result(caller: Caller<Self>, offset: i64, size: i64) -> Result<()> {
         caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
             TracePoint::ZephyrEnvironment,
@@ -428,18 +946,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         );
         let host = caller.data();
 
-        let memory = {
-            let context = host.0.context.borrow();
-            let vm = context
-                .vm
-                .as_ref()
-                .ok_or_else(|| HostError::NoContext)?
-                .upgrade()
-                .ok_or_else(|| HostError::InternalError(InternalError::CannotUpgradeRc))?;
-            let mem_manager = &vm.memory_manager;
-
-            mem_manager.memory
-        };
+        let memory = Self::guest_memory(&caller)?;
 
         let segment = (offset, size);
         let seg = Self::read_segment_from_memory(&memory, &caller, segment)?;
@@ -450,11 +957,167 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         Ok(())
     }
 
+    fn write_binary_result(caller: Caller<Self>, offset: i64, size: i64) -> Result<()> {
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            "Writing binary invocation result.",
+            false,
+        );
+        let host = caller.data();
+
+        let memory = Self::guest_memory(&caller)?;
+
+        let segment = (offset, size);
+        let result = Self::read_segment_from_memory(&memory, &caller, segment)?;
+
+        *host.0.binary_result.borrow_mut() = Some(result);
+
+        Ok(())
+    }
+
+    fn write_result_chunk(
+        caller: Caller<Self>,
+        offset: i64,
+        size: i64,
+        is_last: i64,
+    ) -> Result<()> {
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            format!("Writing invocation result chunk (last: {}).", is_last != 0),
+            false,
+        );
+        let host = caller.data();
+
+        let memory = Self::guest_memory(&caller)?;
+
+        let segment = (offset, size);
+        let chunk = Self::read_segment_from_memory(&memory, &caller, segment)?;
+
+        let mut pending = host.0.pending_chunks.borrow_mut();
+        let max = *host.0.max_result_bytes.borrow();
+        if pending.len().saturating_add(chunk.len()) > max {
+            host.0.stack_trace.borrow_mut().maybe_add_trace(
+                TracePoint::ZephyrEnvironment,
+                format!("Streamed result exceeds the maximum of {} bytes.", max),
+                true,
+            );
+
+            return Err(HostError::ResultTooLarge(max).into());
+        }
+        pending.extend_from_slice(&chunk);
+
+        if is_last != 0 {
+            *host.0.binary_result.borrow_mut() = Some(std::mem::take(&mut *pending));
+        }
+
+        Ok(())
+    }
+
+    fn write_checkpoint(caller: Caller<Self>, offset: i64, size: i64) -> Result<()> {
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            "Writing program checkpoint.",
+            false,
+        );
+        let host = caller.data();
+
+        let memory = Self::guest_memory(&caller)?;
+
+        let segment = (offset, size);
+        let checkpoint = Self::read_segment_from_memory(&memory, &caller, segment)?;
+
+        *host.0.checkpoint.borrow_mut() = Some(checkpoint);
+
+        Ok(())
+    }
+
+    fn read_last_checkpoint(caller: Caller<Self>) -> Result<(i64, i64)> {
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            "Reading the last program checkpoint.",
+            false,
+        );
+        let checkpoint = caller
+            .data()
+            .0
+            .checkpoint
+            .borrow()
+            .clone()
+            .ok_or_else(|| HostError::NoCheckpoint)?;
+
+        Self::write_to_memory(caller, checkpoint).1
+    }
+
+    fn read_config_value(caller: Caller<Self>, offset: i64, size: i64) -> Result<(i64, i64)> {
+        let host = caller.data();
+
+        let memory = Self::guest_memory(&caller)?;
+
+        let key = Self::read_segment_from_memory(&memory, &caller, (offset, size))?;
+        let key = String::from_utf8(key)?;
+
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            format!("Reading configuration value {}.", key),
+            false,
+        );
+        let value = host
+            .0
+            .config
+            .borrow()
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| HostError::NoConfigValue)?;
+
+        Self::write_to_memory(caller, value.into_bytes()).1
+    }
+
+    /// Sets a configuration value that the program can read by key. This
+    /// lets operators change the behaviour of a program without redeploying it.
+    pub fn set_config(&mut self, key: impl ToString, value: impl ToString) {
+        self.0
+            .config
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string());
+    }
+
+    /// Provides the checkpoint the program last wrote in a previous
+    /// invocation, so that it can resume from it.
+    pub fn add_checkpoint(&mut self, checkpoint: Vec<u8>) {
+        *self.0.checkpoint.borrow_mut() = Some(checkpoint);
+    }
+
+    /// Read the latest checkpoint written by the guest environment, if any.
+    /// This is available even if the invocation did not complete.
+    pub fn read_checkpoint(&self) -> Option<Vec<u8>> {
+        self.0.checkpoint.borrow().clone()
+    }
+
+    /// Returns the number of rows affected by the latest database update
+    /// performed by the guest.
+    pub fn read_affected_rows(&self) -> u64 {
+        *self.0.affected_rows.borrow()
+    }
+
     /// Read a result string potentially written from the guest environment.
     pub fn read_result(&self) -> String {
         self.0.result.borrow().clone()
     }
 
+    /// Clears the results written from the guest environment, so that the
+    /// host can be reused for another invocation.
+    pub fn clear_result(&self) {
+        self.0.result.borrow_mut().clear();
+        *self.0.binary_result.borrow_mut() = None;
+        self.0.pending_chunks.borrow_mut().clear();
+    }
+
+    /// Read the binary result written from the guest environment, if the
+    /// program concluded with raw bytes.
+    pub fn read_result_bytes(&self) -> Option<Vec<u8>> {
+        self.0.binary_result.borrow().clone()
+    }
+
     /// Read the VM's stack trace.
     pub fn read_stack_trace(&self) -> StackTrace {
         self.0.stack_trace.borrow().to_owned()
@@ -470,11 +1133,18 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
     ///  - Database write: retrieves instructions and data to be written specified
     /// by the module and calls the [`DB::write_raw()`] function. Writing to the database
     /// is streamlined to the [`DB`] implementation.
+    /// - Database write with ttl: like the database write, but the written row expires
+    /// after the given number of ledgers.
+    /// - Database batch write: like the database write, but the data segments hold several
+    /// rows which are written with a single call to [`DB::write_batch_raw()`].
     /// - Database read: retrieves instructions for the data to be read by the module
     /// and calls the [`DB::read_raw()`] function. Reading from the database is streamlined
     /// to the [`DB`] implementation.
+    /// - Database read latest: like the database read, but only returns the row with the
+    /// greatest value for the given column, see [`DB::read_latest_raw()`].
     /// - Database update: Retrieves and structures instructions and data used by the [`DB`]
     /// implementation to update a table.
+    /// - Read affected rows: returns the number of rows affected by the latest database update.
     /// - Log function: takes an integer from the module and logs it in the host.
     /// - Stack push function: pushes an integer from the module to the host's pseudo
     /// stack. This is currently the means of communication for unbound intructions between
@@ -482,11 +1152,32 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
     /// - Read ledger close meta: Reads the host's latest ledger meta (if present) and
     /// writes it to the module's memory. Returns the offset and the size of the bytes
     /// written in the binary's memory.
-    pub fn host_functions(&self, store: &mut Store<Host<DB, L>>) -> Vec<FunctionInfo> {
-        let mut store = store;
+    /// - Remaining budget: returns the fuel left to the program, allowing it to conclude
+    /// before trapping on fuel exhaustion.
+    /// - Conclude binary: writes raw bytes as the invocation result, see [`Self::read_result_bytes`].
+    /// - Conclude chunk: appends raw bytes to a streamed binary result, which is complete once `is_last` is set.
+    /// - Memory pages: returns the current size of the module's linear memory in pages.
+    /// - Current ledger sequence: returns the latest ledger sequence of the snapshot, also for programs without a ledger close meta.
+    /// - Read input kind: returns whether the loaded input is a ledger close meta or function arguments, see [`InputKind`].
+    /// - Read config value: writes the operator-provided configuration value for a key, see [`Self::set_config`].
+    /// - Checkpoints: write and read back intermediate program state that the host retains
+    /// even if the invocation traps.
+    /// - Is catchup: returns 1 when the embedder marked the invocation as part of a catchup
+    /// and 0 otherwise.
+    /// - Get host id: returns the id the host is bound to.
+    /// - Sha256: hashes bytes from the module's memory and writes the digest back to it.
+    /// - Verify ed25519: checks an ed25519 signature over a message, returns 1 when valid.
+    /// - Emit event: emits a contract event through the Soroban host, see [`Self::read_events`].
+    ///
+    /// The functions aren't bound to a store, the VMs of a host type share them
+    /// and only define the ones their binary imports, see [`crate::vm::Vm::new`].
+    pub fn host_functions() -> Vec<FunctionInfo<DB, L>> {
+        Self::with_soroban_functions(Self::zephyr_host_functions())
+    }
 
+    fn zephyr_host_functions() -> Vec<FunctionInfo<DB, L>> {
         let db_write_fn = {
-            let wrapped = Func::wrap(&mut store, |caller: Caller<Host<DB, L>>| {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
                 caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
                     TracePoint::DatabaseImpl,
                     format!("Writing to the database implementation."),
@@ -517,16 +1208,82 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             }
         };
 
-        let db_update_fn = {
-            let wrapped = Func::wrap(&mut store, |caller: Caller<Host<DB, L>>| {
+        let db_write_with_ttl_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, ttl: i64| {
                 caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
                     TracePoint::DatabaseImpl,
-                    format!("Updating to the database implementation."),
+                    format!(
+                        "Writing to the database implementation with a ttl of {} ledgers.",
+                        ttl
+                    ),
                     false,
                 );
-
-                let (caller, result) = Self::update_database_raw(caller);
-                let res = if let Some(err) = result.err() {
+                let (caller, result) = match u32::try_from(ttl) {
+                    Ok(ttl) => Self::write_database_raw_with_ttl(caller, ttl),
+                    Err(_) => (caller, Err(HostError::InvalidTtl(ttl).into())),
+                };
+                if let Some(err) = result.err() {
+                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                        TracePoint::DatabaseImpl,
+                        format!(
+                            "Hit error {:?} while writing to the database implementation.",
+                            err
+                        ),
+                        true,
+                    );
+                    ZephyrStatus::from(err) as i64
+                } else {
+                    ZephyrStatus::Success as i64
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "write_raw_with_ttl",
+                wrapped,
+            }
+        };
+
+        let db_write_batch_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::DatabaseImpl,
+                    "Writing a batch of rows to the database implementation.",
+                    false,
+                );
+                let (caller, result) = Self::write_database_batch_raw(caller);
+                if let Some(err) = result.err() {
+                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                        TracePoint::DatabaseImpl,
+                        format!(
+                            "Hit error {:?} while writing a batch to the database implementation.",
+                            err
+                        ),
+                        true,
+                    );
+                    ZephyrStatus::from(err) as i64
+                } else {
+                    ZephyrStatus::Success as i64
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "write_batch_raw",
+                wrapped,
+            }
+        };
+
+        let db_update_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::DatabaseImpl,
+                    format!("Updating to the database implementation."),
+                    false,
+                );
+
+                let (caller, result) = Self::update_database_raw(caller);
+                let res = if let Some(err) = result.err() {
                     caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
                         TracePoint::DatabaseImpl,
                         format!(
@@ -550,8 +1307,20 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             }
         };
 
+        let affected_rows_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                caller.data().read_affected_rows() as i64
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "read_affected_rows",
+                wrapped,
+            }
+        };
+
         let db_read_fn = {
-            let db_read_fn_wrapped = Func::wrap(&mut store, |caller: Caller<Host<DB, L>>| {
+            let db_read_fn_wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
                 caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
                     TracePoint::DatabaseImpl,
                     format!("Reading from the database implementation."),
@@ -560,22 +1329,20 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
 
                 let (caller, result) = Host::read_database_self(caller);
 
-                let res = if let Ok(res) = result {
+                if let Ok(res) = result {
                     (ZephyrStatus::Success as i64, res.0, res.1)
                 } else {
                     let err = result.err().unwrap();
                     caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
                         TracePoint::DatabaseImpl,
                         format!(
-                            "Hit error {:?} while updating to the database implementation.",
+                            "Hit error {:?} while reading from the database implementation.",
                             err
                         ),
                         true,
                     );
                     (ZephyrStatus::from(err) as i64, 0, 0)
-                };
-
-                res
+                }
             });
 
             FunctionInfo {
@@ -585,16 +1352,48 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             }
         };
 
+        let db_read_latest_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, order_column: i64| {
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::DatabaseImpl,
+                    format!("Reading from the database implementation."),
+                    false,
+                );
+
+                let (caller, result) = Host::read_database_latest(caller, order_column);
+
+                if let Ok(res) = result {
+                    (ZephyrStatus::Success as i64, res.0, res.1)
+                } else {
+                    let err = result.err().unwrap();
+                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                        TracePoint::DatabaseImpl,
+                        format!(
+                            "Hit error {:?} while reading from the database implementation.",
+                            err
+                        ),
+                        true,
+                    );
+                    (ZephyrStatus::from(err) as i64, 0, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "read_latest_raw",
+                wrapped,
+            }
+        };
+
         let db_read_as_id_fn = {
-            let db_read_fn_wrapped =
-                Func::wrap(&mut store, |caller: Caller<Host<DB, L>>, id: i64| {
-                    let (caller, result) = Host::read_database_as_id(caller, id);
-                    if let Ok(res) = result {
-                        (ZephyrStatus::Success as i64, res.0, res.1)
-                    } else {
-                        (ZephyrStatus::from(result.err().unwrap()) as i64, 0, 0)
-                    }
-                });
+            let db_read_fn_wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, id: i64| {
+                let (caller, result) = Host::read_database_as_id(caller, id);
+                if let Ok(res) = result {
+                    (ZephyrStatus::Success as i64, res.0, res.1)
+                } else {
+                    (ZephyrStatus::from(result.err().unwrap()) as i64, 0, 0)
+                }
+            });
 
             FunctionInfo {
                 module: "env",
@@ -604,30 +1403,196 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let conclude_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::ZephyrEnvironment,
+                    format!("Writing object of size {:?} to result slot.", size),
+                    false,
+                );
+                Host::write_result(caller, offset, size).unwrap();
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "conclude",
+                wrapped,
+            }
+        };
+
+        let conclude_binary_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                let result = Host::write_binary_result(caller, offset, size);
+
+                if let Ok(_) = result {
+                    ZephyrStatus::Success as i64
+                } else {
+                    ZephyrStatus::from(result.err().unwrap()) as i64
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "conclude_binary",
+                wrapped,
+            }
+        };
+
+        let conclude_chunk_fn = {
+            let wrapped = HostFunc::new(
+                |caller: Caller<Host<DB, L>>, offset: i64, size: i64, is_last: i64| {
+                    let result = Host::write_result_chunk(caller, offset, size, is_last);
+
+                    if let Ok(_) = result {
+                        ZephyrStatus::Success as i64
+                    } else {
+                        ZephyrStatus::from(result.err().unwrap()) as i64
+                    }
+                },
+            );
+
+            FunctionInfo {
+                module: "env",
+                func: "conclude_chunk",
+                wrapped,
+            }
+        };
+
+        let read_config_value_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                let result = Host::read_config_value(caller, offset, size);
+
+                if let Ok(res) = result {
+                    (ZephyrStatus::Success as i64, res.0, res.1)
+                } else {
+                    (ZephyrStatus::from(result.err().unwrap()) as i64, 0, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "read_config_value",
+                wrapped,
+            }
+        };
+
+        let write_checkpoint_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                let result = Host::write_checkpoint(caller, offset, size);
+
+                if let Ok(_) = result {
+                    ZephyrStatus::Success as i64
+                } else {
+                    ZephyrStatus::from(result.err().unwrap()) as i64
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "write_checkpoint",
+                wrapped,
+            }
+        };
+
+        let read_checkpoint_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                let result = Host::read_last_checkpoint(caller);
+
+                if let Ok(res) = result {
+                    (ZephyrStatus::Success as i64, res.0, res.1)
+                } else {
+                    (ZephyrStatus::from(result.err().unwrap()) as i64, 0, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "read_checkpoint",
+                wrapped,
+            }
+        };
+
+        let sha256_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                let (caller, result) = Host::sha256(caller, offset, size);
+
+                if let Ok(res) = result {
+                    (ZephyrStatus::Success as i64, res.0, res.1)
+                } else {
+                    let err = result.err().unwrap();
                     caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
                         TracePoint::ZephyrEnvironment,
-                        format!("Writing object of size {:?} to result slot.", size),
-                        false,
+                        format!("Hit error {:?} while hashing.", err),
+                        true,
+                    );
+                    (ZephyrStatus::from(err) as i64, 0, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "host_sha256",
+                wrapped,
+            }
+        };
+
+        let verify_ed25519_fn = {
+            let wrapped = HostFunc::new(
+                |caller: Caller<Host<DB, L>>,
+                 public_key_part_1: i64,
+                 public_key_part_2: i64,
+                 public_key_part_3: i64,
+                 public_key_part_4: i64,
+                 message_offset: i64,
+                 message_size: i64,
+                 signature_offset: i64,
+                 signature_size: i64| {
+                    let public_key = WrappedMaxBytes::array_from_max_parts::<32>(&[
+                        public_key_part_1,
+                        public_key_part_2,
+                        public_key_part_3,
+                        public_key_part_4,
+                    ]);
+
+                    let result = Host::verify_ed25519(
+                        &caller,
+                        public_key,
+                        (message_offset, message_size),
+                        (signature_offset, signature_size),
                     );
-                    Host::write_result(caller, offset, size).unwrap();
+
+                    match result {
+                        Ok(valid) => (ZephyrStatus::Success as i64, valid as i64),
+                        Err(err) => {
+                            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                                TracePoint::ZephyrEnvironment,
+                                format!("Hit error {:?} while verifying signature.", err),
+                                true,
+                            );
+                            (ZephyrStatus::from(err) as i64, 0)
+                        }
+                    }
                 },
             );
 
             FunctionInfo {
                 module: "env",
-                func: "conclude",
+                func: "host_verify_ed25519",
                 wrapped,
             }
         };
 
-        let send_message_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
-                    let result = Host::send_message(caller, offset, size);
+        let emit_event_fn = {
+            let wrapped = HostFunc::new(
+                |caller: Caller<Host<DB, L>>,
+                 topics_offset: i64,
+                 topics_size: i64,
+                 data_offset: i64,
+                 data_size: i64| {
+                    let result = Host::emit_event(
+                        caller,
+                        (topics_offset, topics_size),
+                        (data_offset, data_size),
+                    );
 
                     if let Ok(_) = result {
                         ZephyrStatus::Success as i64
@@ -637,6 +1602,24 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 },
             );
 
+            FunctionInfo {
+                module: "env",
+                func: "emit_event",
+                wrapped,
+            }
+        };
+
+        let send_message_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                let result = Host::send_message(caller, offset, size);
+
+                if let Ok(_) = result {
+                    ZephyrStatus::Success as i64
+                } else {
+                    ZephyrStatus::from(result.err().unwrap()) as i64
+                }
+            });
+
             FunctionInfo {
                 module: "env",
                 func: "tx_send_message",
@@ -645,59 +1628,153 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let log_fn = {
-            let wrapped = Func::wrap(&mut store, |_: Caller<Host<DB, L>>, param: i64| {
+            let wrapped = HostFunc::new(|_: Caller<Host<DB, L>>, param: i64| {
                 println!("Logged: {}", param);
             });
 
             FunctionInfo {
                 module: "env",
-                func: "zephyr_logger",
+                func: "zephyr_logger",
+                wrapped,
+            }
+        };
+
+        let stack_push_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, param: i64| {
+                let host: &Host<DB, L> = caller.data();
+                host.as_stack_mut().0.push(param);
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "zephyr_stack_push",
+                wrapped,
+            }
+        };
+
+        let read_ledger_meta_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                if let Ok(res) = Host::read_ledger_meta(caller) {
+                    res
+                } else {
+                    // this is also unsafe
+                    // panic!()
+
+                    // current implementation is faulty
+                    // and only serves mocked testing
+                    // purposes. Any attempt to run
+                    // Zephyr without providing the latest
+                    // close meta has a high probability of
+                    // breaking.
+
+                    (0, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "read_ledger_meta",
+                wrapped,
+            }
+        };
+
+        let remaining_budget_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| match caller.get_fuel() {
+                Ok(fuel) => (ZephyrStatus::Success as i64, fuel as i64),
+                Err(error) => {
+                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                        TracePoint::ZephyrEnvironment,
+                        "Fuel metering is not enabled, cannot read the remaining budget.",
+                        true,
+                    );
+                    (ZephyrStatus::from(anyhow::Error::from(error)) as i64, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "remaining_budget",
+                wrapped,
+            }
+        };
+
+        let memory_pages_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                let result = Host::memory_pages(&caller);
+
+                if let Ok(pages) = result {
+                    (ZephyrStatus::Success as i64, pages as i64)
+                } else {
+                    (ZephyrStatus::from(result.err().unwrap()) as i64, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "memory_pages",
+                wrapped,
+            }
+        };
+
+        let current_ledger_sequence_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                let result = Host::current_ledger_sequence(&caller);
+
+                if let Ok(sequence) = result {
+                    (ZephyrStatus::Success as i64, sequence as i64)
+                } else {
+                    (ZephyrStatus::from(result.err().unwrap()) as i64, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "current_ledger_sequence",
+                wrapped,
+            }
+        };
+
+        let read_input_kind_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| {
+                let result = Host::read_input_kind(&caller);
+
+                if let Ok(kind) = result {
+                    (ZephyrStatus::Success as i64, kind as i64)
+                } else {
+                    (ZephyrStatus::from(result.err().unwrap()) as i64, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "read_input_kind",
                 wrapped,
             }
         };
 
-        let stack_push_fn = {
-            let wrapped = Func::wrap(&mut store, |caller: Caller<Host<DB, L>>, param: i64| {
-                let host: &Host<DB, L> = caller.data();
-                host.as_stack_mut().0.push(param);
-            });
+        let is_catchup_fn = {
+            let wrapped =
+                HostFunc::new(|caller: Caller<Host<DB, L>>| caller.data().is_catchup() as i64);
 
             FunctionInfo {
                 module: "env",
-                func: "zephyr_stack_push",
+                func: "is_catchup",
                 wrapped,
             }
         };
 
-        let read_ledger_meta_fn = {
-            let wrapped = Func::wrap(&mut store, |caller: Caller<Host<DB, L>>| {
-                if let Ok(res) = Host::read_ledger_meta(caller) {
-                    res
-                } else {
-                    // this is also unsafe
-                    // panic!()
-
-                    // current implementation is faulty
-                    // and only serves mocked testing
-                    // purposes. Any attempt to run
-                    // Zephyr without providing the latest
-                    // close meta has a high probability of
-                    // breaking.
-
-                    (0, 0)
-                }
-            });
+        let get_host_id_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>| caller.data().get_host_id());
 
             FunctionInfo {
                 module: "env",
-                func: "read_ledger_meta",
+                func: "get_host_id",
                 wrapped,
             }
         };
 
         let read_contract_data_entry_by_contract_id_and_key_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>,
                  contract_part_1: i64,
                  contract_part_2: i64,
@@ -734,8 +1811,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let read_contract_instance_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>,
                  contract_part_1: i64,
                  contract_part_2: i64,
@@ -771,9 +1847,32 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             }
         };
 
+        let read_contract_instances_fn = {
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                let (caller, result) = Host::read_contract_instances(caller, offset, size);
+
+                if let Ok(res) = result {
+                    (ZephyrStatus::Success as i64, res.0, res.1)
+                } else {
+                    let err = result.err().unwrap();
+                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                        TracePoint::LedgerImpl,
+                        format!("Hit error {:?} while reading contract instances.", err),
+                        true,
+                    );
+                    (ZephyrStatus::from(err) as i64, 0, 0)
+                }
+            });
+
+            FunctionInfo {
+                module: "env",
+                func: "read_contract_instances",
+                wrapped,
+            }
+        };
+
         let read_contract_entries_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>,
                  contract_part_1: i64,
                  contract_part_2: i64,
@@ -812,9 +1911,51 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             }
         };
 
+        let read_contract_entries_by_prefix_fn = {
+            let wrapped = HostFunc::new(
+                |caller: Caller<Host<DB, L>>,
+                 contract_part_1: i64,
+                 contract_part_2: i64,
+                 contract_part_3: i64,
+                 contract_part_4: i64,
+                 offset: i64,
+                 size: i64| {
+                    let contract = WrappedMaxBytes::array_from_max_parts::<32>(&[
+                        contract_part_1,
+                        contract_part_2,
+                        contract_part_3,
+                        contract_part_4,
+                    ]);
+
+                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                        TracePoint::LedgerImpl,
+                        format!(
+                            "Reading contract entries matching a key prefix for contract {:?}.",
+                            contract
+                        ),
+                        false,
+                    );
+
+                    let (caller, result) =
+                        Host::read_contract_entries_by_prefix(caller, contract, offset, size);
+
+                    if let Ok(res) = result {
+                        (ZephyrStatus::Success as i64, res.0, res.1)
+                    } else {
+                        (ZephyrStatus::from(result.err().unwrap()) as i64, 0, 0)
+                    }
+                },
+            );
+
+            FunctionInfo {
+                module: "env",
+                func: "read_contract_entries_by_contract_and_prefix",
+                wrapped,
+            }
+        };
+
         let read_contract_entries_to_env_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>,
                  contract_part_1: i64,
                  contract_part_2: i64,
@@ -854,8 +1995,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let read_account_from_ledger_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>,
                  account_part_1: i64,
                  account_part_2: i64,
@@ -890,37 +2030,53 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 wrapped,
             }
         };
-        
-        let use_soroban_functions = true;
 
-        let mut all_exports = if use_soroban_functions {
-            soroban_host_gen::generate_host_fn_infos(store)
-        } else {
-            vec![]
-        };
-
-        let mut arr = vec![
+        vec![
             db_write_fn,
+            db_write_with_ttl_fn,
+            db_write_batch_fn,
             db_read_fn,
+            db_read_latest_fn,
             db_update_fn,
+            affected_rows_fn,
             log_fn,
             stack_push_fn,
             read_ledger_meta_fn,
+            remaining_budget_fn,
+            memory_pages_fn,
+            read_input_kind_fn,
+            current_ledger_sequence_fn,
+            is_catchup_fn,
+            get_host_id_fn,
             read_contract_data_entry_by_contract_id_and_key_fn,
             read_contract_instance_fn,
+            read_contract_instances_fn,
             read_contract_entries_fn,
+            read_contract_entries_by_prefix_fn,
             read_contract_entries_to_env_fn,
             conclude_fn,
+            conclude_binary_fn,
+            conclude_chunk_fn,
+            write_checkpoint_fn,
+            read_checkpoint_fn,
+            read_config_value_fn,
             send_message_fn,
+            sha256_fn,
+            verify_ed25519_fn,
+            emit_event_fn,
             db_read_as_id_fn,
-            read_account_from_ledger_fn,            
-        ];
+            read_account_from_ledger_fn,
+        ]
+    }
+
+    /// Adds the soroban host functions to the given Zephyr host functions.
+    fn with_soroban_functions(
+        mut zephyr_functions: Vec<FunctionInfo<DB, L>>,
+    ) -> Vec<FunctionInfo<DB, L>> {
+        let mut all_exports = soroban_host_gen::generate_host_fn_infos();
+        all_exports.append(&mut zephyr_functions);
+        all_exports.append(&mut Self::soroban_adjusted());
 
-        all_exports.append(&mut arr);
-        if use_soroban_functions {
-            all_exports.append(&mut Self::soroban_adjusted(store));
-        }
-        
         // we reverse because we let the linker error when adding the original soroban functions.
         // we should probably just trim the soroban host function generation and exclude the functions
         // we tamper with in `soroban_adjusted`.
@@ -929,64 +2085,66 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         all_exports
     }
 
-    fn soroban_adjusted(mut store: &mut Store<Host<DB, L>>) -> Vec<FunctionInfo> {
+    fn soroban_adjusted() -> Vec<FunctionInfo<DB, L>> {
         let scval_to_valid_host_val = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
-                    let bytes = {
-                        let host: &Self = caller.data();
-                        let memory = {
-                            let context = host.0.context.borrow();
-                            let vm = context
-                                .vm
-                                .as_ref()
-                                .ok_or_else(|| HostError::NoContext)
-                                .unwrap()
-                                .upgrade()
-                                .ok_or_else(|| {
-                                    HostError::InternalError(InternalError::CannotUpgradeRc)
-                                })
-                                .unwrap();
-                            let mem_manager = &vm.memory_manager;
-
-                            mem_manager.memory
-                        };
-
-                        let segment = (offset, size);
-                        Self::read_segment_from_memory(&memory, &caller, segment).unwrap()
-                    };
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, offset: i64, size: i64| {
+                let bytes = {
+                    let memory = Self::guest_memory(&caller).unwrap();
 
-                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    let segment = (offset, size);
+                    Self::read_segment_from_memory(&memory, &caller, segment).unwrap()
+                };
+
+                caller
+                    .data()
+                    .0
+                    .stack_trace
+                    .borrow_mut()
+                    .maybe_add_payload_trace(
                         TracePoint::SorobanEnvironment,
-                        format!("Building ScVal from bytes {:?}.", bytes),
+                        &bytes,
+                        |bytes| format!("Building ScVal from bytes {}.", bytes),
                         false,
                     );
-                    let scval = ScVal::from_xdr(bytes, Limits::none()).unwrap();
-
-                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                let scval = ScVal::from_xdr(bytes, Limits::none()).unwrap();
+
+                caller
+                    .data()
+                    .0
+                    .stack_trace
+                    .borrow_mut()
+                    .maybe_add_payload_trace(
                         TracePoint::SorobanEnvironment,
-                        format!("Converting ScVal {:?} to a valid host value.", scval),
+                        &scval,
+                        |scval| format!("Converting ScVal {} to a valid host value.", scval),
                         false,
                     );
-                    let (caller, result) = Host::scval_to_valid_host_val(caller, scval.clone());
+                let (caller, result) = Host::scval_to_valid_host_val(caller, scval.clone());
 
-                    if let Ok(res) = result {
-                        (ZephyrStatus::Success as i64, res)
-                    } else {
-                        let error = result.err();
-                        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                if let Ok(res) = result {
+                    (ZephyrStatus::Success as i64, res)
+                } else {
+                    let error = result.err();
+                    let message = |scval: &str| {
+                        format!(
+                            "Hit error {:?} while converting ScVal {} to a valid host value.",
+                            error, scval
+                        )
+                    };
+                    caller
+                        .data()
+                        .0
+                        .stack_trace
+                        .borrow_mut()
+                        .maybe_add_payload_trace(
                             TracePoint::SorobanEnvironment,
-                            format!(
-                                "Hit error {:?} while converting ScVal {:?} to a valid host value.",
-                                error, scval
-                            ),
+                            &scval,
+                            message,
                             true,
                         );
-                        (ZephyrStatus::from(error.unwrap()) as i64, 0)
-                    }
-                },
-            );
+                    (ZephyrStatus::from(error.unwrap()) as i64, 0)
+                }
+            });
 
             FunctionInfo {
                 module: "env",
@@ -994,14 +2152,20 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 wrapped,
             }
         };
-        
+
         let valid_host_val_to_scval = {
-            let wrapped = Func::wrap(&mut store, |caller: Caller<Host<DB, L>>, val: i64| {
-                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                    TracePoint::SorobanEnvironment,
-                    format!("Converting host val {:?} to ScVal.", val),
-                    false,
-                );
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, val: i64| {
+                caller
+                    .data()
+                    .0
+                    .stack_trace
+                    .borrow_mut()
+                    .maybe_add_payload_trace(
+                        TracePoint::SorobanEnvironment,
+                        &val,
+                        |val| format!("Converting host val {} to ScVal.", val),
+                        false,
+                    );
                 let (caller, result) =
                     Host::valid_host_val_to_scval(caller, Val::from_payload(val as u64));
 
@@ -1030,41 +2194,38 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let string_from_linmem = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
-                    let vm_ctx = CustomVMCtx::new(&caller);
-                    let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
+                let vm_ctx = CustomVMCtx::new(&caller);
+                let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
 
-                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                        TracePoint::SorobanEnvironment,
-                        "Creating soroban string from ZVM linear memory.",
-                        false,
-                    );
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::SorobanEnvironment,
+                    "Creating soroban string from ZVM linear memory.",
+                    false,
+                );
 
-                    let effect = |host: soroban_env_host::Host| {
-                        let result: Result<_, soroban_env_host::HostError> = host
-                            .string_new_from_linear_memory_mem(
-                                vm_ctx,
-                                build_u32val(&host, lm_pos)?,
-                                build_u32val(&host, len)?,
-                            );
+                let effect = |host: soroban_env_host::Host| {
+                    let result: Result<_, soroban_env_host::HostError> = host
+                        .string_new_from_linear_memory_mem(
+                            vm_ctx,
+                            build_u32val(&host, lm_pos)?,
+                            build_u32val(&host, len)?,
+                        );
 
-                        with_frame(host, result)
-                    };
+                    with_frame(host, result)
+                };
 
-                    let val = effect(host);
-                    match val {
-                        Ok(val) => val.get_payload() as i64,
-                        Err(host_error) => {
-                            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while reating soroban string from ZVM linear memory.", host_error), true);
-                            // todo log error.
-                            // Note: this will panic on the guest.
-                            0
-                        }
+                let val = effect(host);
+                match val {
+                    Ok(val) => val.get_payload() as i64,
+                    Err(host_error) => {
+                        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while reating soroban string from ZVM linear memory.", host_error), true);
+                        // todo log error.
+                        // Note: this will panic on the guest.
+                        0
                     }
-                },
-            );
+                }
+            });
 
             FunctionInfo {
                 module: "b",
@@ -1074,41 +2235,38 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let symbol_from_linmem = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
-                    let vm_ctx = CustomVMCtx::new(&caller);
-                    let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
+                let vm_ctx = CustomVMCtx::new(&caller);
+                let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
 
-                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                        TracePoint::SorobanEnvironment,
-                        format!("Creating soroban symbol from ZVM linear memory."),
-                        false,
-                    );
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::SorobanEnvironment,
+                    format!("Creating soroban symbol from ZVM linear memory."),
+                    false,
+                );
 
-                    let effect = |host: soroban_env_host::Host| {
-                        let result: Result<_, soroban_env_host::HostError> = host
-                            .symbol_new_from_linear_memory_mem(
-                                vm_ctx,
-                                build_u32val(&host, lm_pos)?,
-                                build_u32val(&host, len)?,
-                            );
+                let effect = |host: soroban_env_host::Host| {
+                    let result: Result<_, soroban_env_host::HostError> = host
+                        .symbol_new_from_linear_memory_mem(
+                            vm_ctx,
+                            build_u32val(&host, lm_pos)?,
+                            build_u32val(&host, len)?,
+                        );
 
-                        with_frame(host, result)
-                    };
+                    with_frame(host, result)
+                };
 
-                    let val = effect(host);
-                    match val {
-                        Ok(val) => val.get_payload() as i64,
-                        Err(host_error) => {
-                            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while creating soroban string from ZVM linear memory.", host_error), true);
-                            // todo log error.
-                            // Note: this will panic on the guest.
-                            0
-                        }
+                let val = effect(host);
+                match val {
+                    Ok(val) => val.get_payload() as i64,
+                    Err(host_error) => {
+                        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while creating soroban string from ZVM linear memory.", host_error), true);
+                        // todo log error.
+                        // Note: this will panic on the guest.
+                        0
                     }
-                },
-            );
+                }
+            });
 
             FunctionInfo {
                 module: "b",
@@ -1118,8 +2276,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let symbol_index_from_linmem = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>, sym: i64, lm_pos: i64, len: i64| {
                     let vm_ctx = CustomVMCtx::new(&caller);
                     let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
@@ -1171,42 +2328,39 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let vec_new_from_linear_memory_mem = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
-                    let vm_ctx = CustomVMCtx::new(&caller);
-                    let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
+                let vm_ctx = CustomVMCtx::new(&caller);
+                let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
 
-                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                        TracePoint::SorobanEnvironment,
-                        format!("Creating soroban vector from ZVM linear memory."),
-                        false,
-                    );
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::SorobanEnvironment,
+                    format!("Creating soroban vector from ZVM linear memory."),
+                    false,
+                );
 
-                    let effect = |host: soroban_env_host::Host| {
-                        let res: Result<_, soroban_env_host::HostError> = host
-                            .vec_new_from_linear_memory_mem(
-                                vm_ctx,
-                                build_u32val(&host, lm_pos)?,
-                                build_u32val(&host, len)?,
-                            );
+                let effect = |host: soroban_env_host::Host| {
+                    let res: Result<_, soroban_env_host::HostError> = host
+                        .vec_new_from_linear_memory_mem(
+                            vm_ctx,
+                            build_u32val(&host, lm_pos)?,
+                            build_u32val(&host, len)?,
+                        );
 
-                        with_frame(host, res)
-                    };
+                    with_frame(host, res)
+                };
 
-                    let val = effect(host);
-                    match val {
-                        Ok(val) => val.get_payload() as i64,
-                        Err(host_error) => {
-                            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while creating soroban vector from ZVM linear memory.", host_error), true);
+                let val = effect(host);
+                match val {
+                    Ok(val) => val.get_payload() as i64,
+                    Err(host_error) => {
+                        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while creating soroban vector from ZVM linear memory.", host_error), true);
 
-                            // todo log error.
-                            // Note: this will panic on the guest.
-                            0
-                        }
+                        // todo log error.
+                        // Note: this will panic on the guest.
+                        0
                     }
-                },
-            );
+                }
+            });
 
             FunctionInfo {
                 module: "v",
@@ -1216,8 +2370,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let map_new_from_linear_memory_mem = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>, key_pos: i64, val_pos: i64, len: i64| {
                     let vm_ctx = CustomVMCtx::new(&caller);
                     let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
@@ -1260,40 +2413,37 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let bytes_new_from_linear_memory_mem = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
-                    let vm_ctx = CustomVMCtx::new(&caller);
-                    let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, lm_pos: i64, len: i64| {
+                let vm_ctx = CustomVMCtx::new(&caller);
+                let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
 
-                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                        TracePoint::SorobanEnvironment,
-                        format!("Creating soroban bytes from ZVM linear memory."),
-                        false,
-                    );
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::SorobanEnvironment,
+                    format!("Creating soroban bytes from ZVM linear memory."),
+                    false,
+                );
 
-                    let effect = |host: soroban_env_host::Host| {
-                        let res: Result<_, soroban_env_host::HostError> = host
-                            .bytes_new_from_linear_memory_mem(
-                                vm_ctx,
-                                build_u32val(&host, lm_pos)?,
-                                build_u32val(&host, len)?,
-                            );
-                        with_frame(host, res)
-                    };
+                let effect = |host: soroban_env_host::Host| {
+                    let res: Result<_, soroban_env_host::HostError> = host
+                        .bytes_new_from_linear_memory_mem(
+                            vm_ctx,
+                            build_u32val(&host, lm_pos)?,
+                            build_u32val(&host, len)?,
+                        );
+                    with_frame(host, res)
+                };
 
-                    let val = effect(host);
-                    match val {
-                        Ok(val) => val.get_payload() as i64,
-                        Err(host_error) => {
-                            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while creating soroban bytes from ZVM linear memory.", host_error), true);
-                            // todo log error.
-                            // Note: this will panic on the guest.
-                            0
-                        }
+                let val = effect(host);
+                match val {
+                    Ok(val) => val.get_payload() as i64,
+                    Err(host_error) => {
+                        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::SorobanEnvironment, format!("Hit error {:?} while creating soroban bytes from ZVM linear memory.", host_error), true);
+                        // todo log error.
+                        // Note: this will panic on the guest.
+                        0
                     }
-                },
-            );
+                }
+            });
 
             FunctionInfo {
                 module: "b",
@@ -1303,8 +2453,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let bytes_copy_to_linear_memory_mem = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>, b: i64, b_pos: i64, lm_pos: i64, len: i64| {
                     let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
 
@@ -1370,8 +2519,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let map_unpack_to_linear_memory_fn_mem = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>, map: i64, keys_pos: i64, vals_pos: i64, len: i64| {
                     let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
 
@@ -1432,21 +2580,18 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let i128_from_pieces = {
-            let wrapped = Func::wrap(
-                &mut store,
-                |caller: Caller<Host<DB, L>>, obj: i64| {
-                    let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
-                    println!("\n\n\ncalled");
+            let wrapped = HostFunc::new(|caller: Caller<Host<DB, L>>, obj: i64| {
+                let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
+                println!("\n\n\ncalled");
 
-                    caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                        TracePoint::SorobanEnvironment,
-                        format!("I128 from pieces."),
-                        false,
-                    );
+                caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                    TracePoint::SorobanEnvironment,
+                    format!("I128 from pieces."),
+                    false,
+                );
 
-                    0i64
-                },
-            );
+                0i64
+            });
 
             FunctionInfo {
                 module: "i",
@@ -1456,8 +2601,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let vec_unpack_to_linear_memory_fn_mem = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>, vec: i64, vals_pos: i64, len: i64| {
                     let host: soroban_env_host::Host = Host::<DB, L>::soroban_host(&caller);
 
@@ -1517,8 +2661,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         };
 
         let soroban_simulate_tx_fn = {
-            let wrapped = Func::wrap(
-                &mut store,
+            let wrapped = HostFunc::new(
                 |caller: Caller<Host<DB, L>>,
                  account_part_1: i64,
                  account_part_2: i64,
@@ -1563,7 +2706,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             soroban_simulate_tx_fn,
             bytes_copy_to_linear_memory_mem,
             map_new_from_linear_memory_mem,
-            i128_from_pieces
+            i128_from_pieces,
         ]
     }
 }
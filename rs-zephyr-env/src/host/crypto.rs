@@ -0,0 +1,86 @@
+use super::Host;
+use crate::{
+    db::{database::ZephyrDatabase, ledger::LedgerStateRead},
+    error::HostError,
+    trace::TracePoint,
+};
+use anyhow::Result;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+use wasmi::Caller;
+
+impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB, L> {
+    pub(crate) fn sha256(
+        caller: Caller<Self>,
+        offset: i64,
+        size: i64,
+    ) -> (Caller<Self>, Result<(i64, i64)>) {
+        let effect = (|| {
+            let bytes = {
+                let memory = Self::guest_memory(&caller)?;
+
+                let segment = (offset, size);
+                Self::read_segment_from_memory(&memory, &caller, segment)?
+            };
+
+            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                TracePoint::ZephyrEnvironment,
+                format!("Hashing {} bytes with sha256.", bytes.len()),
+                false,
+            );
+
+            let mut hasher = Sha256::new();
+            hasher.update(bytes);
+
+            Ok(hasher.finalize().to_vec())
+        })();
+
+        let hash = if let Ok(hash) = effect {
+            hash
+        } else {
+            return (caller, Err(effect.err().unwrap()));
+        };
+
+        Self::write_to_memory(caller, hash)
+    }
+
+    pub(crate) fn verify_ed25519(
+        caller: &Caller<Self>,
+        public_key: [u8; 32],
+        message: (i64, i64),
+        signature: (i64, i64),
+    ) -> Result<bool> {
+        let (message, signature) = {
+            let memory = Self::guest_memory(caller)?;
+
+            (
+                Self::read_segment_from_memory(&memory, caller, message)?,
+                Self::read_segment_from_memory(&memory, caller, signature)?,
+            )
+        };
+
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            format!("Verifying ed25519 signature over {} bytes.", message.len()),
+            false,
+        );
+
+        verify_ed25519_signature(&public_key, &message, &signature)
+    }
+}
+
+/// Verifies an ed25519 signature. Keys that aren't valid curve points and
+/// signatures that aren't 64 bytes long are errors rather than invalid
+/// signatures.
+pub(crate) fn verify_ed25519_signature(
+    public_key: &[u8; 32],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool> {
+    let public_key =
+        VerifyingKey::from_bytes(public_key).map_err(|_| HostError::InvalidEd25519PublicKey)?;
+    let signature =
+        Signature::from_slice(signature).map_err(|_| HostError::InvalidEd25519Signature)?;
+
+    Ok(public_key.verify(message, &signature).is_ok())
+}
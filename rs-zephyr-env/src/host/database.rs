@@ -9,14 +9,45 @@ use crate::{
         database::{DatabasePermissions, WhereCond, ZephyrDatabase},
         ledger::LedgerStateRead,
     },
-    error::{HostError, InternalError},
+    error::HostError,
+    stack::StackSection,
     trace::TracePoint,
 };
 
 use super::{utils, Host};
 
+/// How the data segments pushed by the guest are written.
+enum WriteMode {
+    /// The segments form a single row.
+    Row,
+
+    /// The segments form a single row expiring after the given number of ledgers.
+    RowWithTtl(u32),
+
+    /// The segments form consecutive rows with the same columns.
+    Batch,
+}
+
 impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB, L> {
     pub(crate) fn write_database_raw(caller: Caller<Self>) -> (Caller<Self>, Result<()>) {
+        Self::write_database(caller, WriteMode::Row)
+    }
+
+    pub(crate) fn write_database_raw_with_ttl(
+        caller: Caller<Self>,
+        ttl_ledgers: u32,
+    ) -> (Caller<Self>, Result<()>) {
+        Self::write_database(caller, WriteMode::RowWithTtl(ttl_ledgers))
+    }
+
+    /// Writes several rows to the same table with a single host call. The
+    /// instructions are the same as for a single row, with the data segments
+    /// of all rows pushed one after the other.
+    pub(crate) fn write_database_batch_raw(caller: Caller<Self>) -> (Caller<Self>, Result<()>) {
+        Self::write_database(caller, WriteMode::Batch)
+    }
+
+    fn write_database(caller: Caller<Self>, mode: WriteMode) -> (Caller<Self>, Result<()>) {
         let effect = (|| {
             let (memory, write_point_hash, columns, segments) = {
                 let host = caller.data();
@@ -32,8 +63,11 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     "Reading the table name.",
                     false,
                 );
+                stack_impl.0.detect_protocol();
                 let write_point_hash: [u8; 16] = {
+                    stack_impl.0.expect_section(StackSection::TableName)?;
                     let point_raw = stack_impl.0.get_with_step()?;
+                    host.check_table_access(point_raw)?;
                     let point_bytes = utils::bytes::i64_to_bytes(point_raw);
                     md5::compute([point_bytes, id].concat()).into()
                 };
@@ -44,6 +78,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     false,
                 );
                 let columns = {
+                    stack_impl.0.expect_section(StackSection::Columns)?;
                     let columns_size_idx = stack_impl.0.get_with_step()?;
                     let mut columns: Vec<i64> = Vec::new();
                     for _ in 0..columns_size_idx as usize {
@@ -61,6 +96,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     false,
                 );
                 let data_segments = {
+                    stack_impl.0.expect_section(StackSection::Segments)?;
                     let mut segments: Vec<(i64, i64)> = Vec::new();
                     let data_segments_size_idx = {
                         let non_fixed = stack_impl.0.get_with_step()?;
@@ -74,17 +110,10 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     segments
                 };
 
-                let context = host.0.context.borrow();
-                let vm = context
-                    .vm
-                    .as_ref()
-                    .ok_or_else(|| HostError::NoContext)?
-                    .upgrade()
-                    .ok_or_else(|| HostError::InternalError(InternalError::CannotUpgradeRc))?;
-                let mem_manager = &vm.memory_manager;
+                let memory = Self::guest_memory(&caller)?;
                 stack_impl.0.clear();
 
-                (mem_manager.memory, write_point_hash, columns, data_segments)
+                (memory, write_point_hash, columns, data_segments)
             };
 
             caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
@@ -110,12 +139,37 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 }
 
                 caller.data().0.stack_trace.borrow_mut().maybe_add_trace(TracePoint::DatabaseImpl, format!("Delegating database insertion instructions to generic database implementation."), false);
-                db_impl.db.write_raw(
-                    host.get_host_id(),
-                    write_point_hash,
-                    &columns,
-                    aggregated_data,
-                )?;
+                match mode {
+                    WriteMode::Row => db_impl.db.write_raw(
+                        host.get_host_id(),
+                        write_point_hash,
+                        &columns,
+                        aggregated_data,
+                    )?,
+                    WriteMode::RowWithTtl(ttl_ledgers) => db_impl.db.write_raw_with_ttl(
+                        host.get_host_id(),
+                        write_point_hash,
+                        &columns,
+                        aggregated_data,
+                        ttl_ledgers,
+                    )?,
+                    WriteMode::Batch => {
+                        if columns.is_empty() || aggregated_data.len() % columns.len() != 0 {
+                            return Err(DatabaseError::ZephyrQueryMalformed.into());
+                        }
+
+                        let rows = aggregated_data
+                            .chunks(columns.len())
+                            .map(|row| row.to_vec())
+                            .collect();
+                        db_impl.db.write_batch_raw(
+                            host.get_host_id(),
+                            write_point_hash,
+                            &columns,
+                            rows,
+                        )?;
+                    }
+                }
             };
 
             Ok(())
@@ -124,7 +178,22 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         (caller, effect)
     }
 
+    /// Rejects access to tables that are not allowed by the embedder, see
+    /// [`Self::set_allowed_tables`].
+    pub(crate) fn check_table_access(&self, table: i64) -> Result<()> {
+        if let Some(allowed) = self.0.allowed_tables.borrow().as_ref() {
+            if !allowed.contains(&table) {
+                return Err(HostError::TableNotAllowed.into());
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn update_database_raw(caller: Caller<Self>) -> (Caller<Self>, Result<()>) {
+        // A failed update must not report the rows affected by the previous one.
+        *caller.data().0.affected_rows.borrow_mut() = 0;
+
         let effect = (|| {
             let (memory, write_point_hash, columns, segments, conditions, conditions_args) = {
                 let host = caller.data();
@@ -141,8 +210,11 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     "Reading the table name.",
                     false,
                 );
+                stack_impl.0.detect_protocol();
                 let write_point_hash: [u8; 16] = {
+                    stack_impl.0.expect_section(StackSection::TableName)?;
                     let point_raw = stack_impl.0.get_with_step()?;
+                    host.check_table_access(point_raw)?;
                     let point_bytes = utils::bytes::i64_to_bytes(point_raw);
                     md5::compute([point_bytes, id].concat()).into()
                 };
@@ -153,6 +225,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     false,
                 );
                 let columns = {
+                    stack_impl.0.expect_section(StackSection::Columns)?;
                     let columns_size_idx = stack_impl.0.get_with_step()?;
                     let mut columns: Vec<i64> = Vec::new();
 
@@ -172,6 +245,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     false,
                 );
                 let data_segments = {
+                    stack_impl.0.expect_section(StackSection::Segments)?;
                     let mut segments: Vec<(i64, i64)> = Vec::new();
 
                     let data_segments_size_idx = {
@@ -196,6 +270,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     false,
                 );
                 let conditions = {
+                    stack_impl.0.expect_section(StackSection::Conditions)?;
                     let mut conditions = Vec::new();
 
                     let conditions_length = {
@@ -221,6 +296,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     false,
                 );
                 let conditions_args = {
+                    stack_impl.0.expect_section(StackSection::ConditionArgs)?;
                     let mut segments = Vec::new();
 
                     let args_length = {
@@ -237,19 +313,12 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                     segments
                 };
 
-                let context = host.0.context.borrow();
-                let vm = context
-                    .vm
-                    .as_ref()
-                    .ok_or_else(|| HostError::NoContext)?
-                    .upgrade()
-                    .ok_or_else(|| HostError::InternalError(InternalError::CannotUpgradeRc))?;
-                let mem_manager = &vm.memory_manager;
+                let memory = Self::guest_memory(&caller)?;
 
                 stack_impl.0.clear();
 
                 (
-                    mem_manager.memory,
+                    memory,
                     write_point_hash,
                     columns,
                     data_segments,
@@ -299,7 +368,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 ),
                 false,
             );
-            db_impl.db.update_raw(
+            let affected = db_impl.db.update_raw(
                 host.get_host_id(),
                 write_point_hash,
                 &columns,
@@ -308,6 +377,8 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 aggregated_conditions_args,
             )?;
 
+            *host.0.affected_rows.borrow_mut() = affected;
+
             Ok(())
         })();
 
@@ -321,7 +392,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
     ) -> (Caller<Self>, Result<(i64, i64)>) {
         let host = caller.data();
 
-        let raw_read = host.read_database_raw(host_id, &caller);
+        let raw_read = host.read_database_raw(host_id, &caller, None);
         let read = if let Ok(read) = raw_read {
             read
         } else {
@@ -335,7 +406,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         let host = caller.data();
         let host_id = host.get_host_id();
 
-        let raw_read = host.read_database_raw(host_id, &caller);
+        let raw_read = host.read_database_raw(host_id, &caller, None);
         let read = if let Ok(read) = raw_read {
             read
         } else {
@@ -344,7 +415,29 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         Self::write_to_memory(caller, read)
     }
 
-    pub(crate) fn read_database_raw(&self, host_id: i64, caller: &Caller<Self>) -> Result<Vec<u8>> {
+    /// Reads only the row with the greatest `order_column` value.
+    pub(crate) fn read_database_latest(
+        caller: Caller<Self>,
+        order_column: i64,
+    ) -> (Caller<Self>, Result<(i64, i64)>) {
+        let host = caller.data();
+        let host_id = host.get_host_id();
+
+        let raw_read = host.read_database_raw(host_id, &caller, Some(order_column));
+        let read = if let Ok(read) = raw_read {
+            read
+        } else {
+            return (caller, Err(raw_read.err().unwrap()));
+        };
+        Self::write_to_memory(caller, read)
+    }
+
+    pub(crate) fn read_database_raw(
+        &self,
+        host_id: i64,
+        caller: &Caller<Self>,
+        latest_by: Option<i64>,
+    ) -> Result<Vec<u8>> {
         //let host = caller.data();
         let host = self;
         let read = {
@@ -364,8 +457,11 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 "Reading the table name.",
                 false,
             );
+            stack_impl.detect_protocol();
             let read_point_hash: [u8; 16] = {
+                stack_impl.expect_section(StackSection::TableName)?;
                 let point_raw = stack_impl.get_with_step()?;
+                host.check_table_access(point_raw)?;
                 let point_bytes = utils::bytes::i64_to_bytes(point_raw);
 
                 md5::compute([point_bytes, id].concat()).into()
@@ -377,6 +473,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 false,
             );
             let read_data = {
+                stack_impl.expect_section(StackSection::Columns)?;
                 let data_size_idx = stack_impl.get_with_step()?;
                 let mut retrn = Vec::new();
 
@@ -396,11 +493,11 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             );
             let conditions = {
                 let mut conditions = Vec::new();
-                let non_fixed = stack_impl.get_with_step();
 
                 // Note: if there is an extra argument here specifying the conditions length
                 // we assume that it's safe to halt execution if the subsequent stack is malformed
-                if let Ok(non_fixed) = non_fixed {
+                if stack_impl.has_section(StackSection::Conditions) {
+                    let non_fixed = stack_impl.get_with_step()?;
                     let conditions_length = (non_fixed * 2) as usize;
 
                     for _ in (0..conditions_length).step_by(2) {
@@ -425,6 +522,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 false,
             );
             let conditions_args = if has_conditions {
+                stack_impl.expect_section(StackSection::ConditionArgs)?;
                 let mut segments = Vec::new();
 
                 let args_length = {
@@ -474,13 +572,24 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 ),
                 false,
             );
-            db_impl.db.read_raw(
-                user_id,
-                read_point_hash,
-                &read_data,
-                conditions.as_ref().map(Vec::as_slice),
-                aggregated_conditions_args,
-            )?
+            if let Some(order_column) = latest_by {
+                db_impl.db.read_latest_raw(
+                    user_id,
+                    read_point_hash,
+                    &read_data,
+                    conditions.as_ref().map(Vec::as_slice),
+                    aggregated_conditions_args,
+                    order_column,
+                )?
+            } else {
+                db_impl.db.read_raw(
+                    user_id,
+                    read_point_hash,
+                    &read_data,
+                    conditions.as_ref().map(Vec::as_slice),
+                    aggregated_conditions_args,
+                )?
+            }
         };
 
         Ok(read)
@@ -77,17 +77,20 @@ impl<'a, DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Cus
 impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB, L> {
     /// Returns wasmi's VM memory handler.
     pub fn get_memory(caller: &Caller<Self>) -> Memory {
-        let host = caller.data();
-
-        let memory = {
-            let context = host.0.context.borrow();
-            let vm = context.vm.as_ref().unwrap().upgrade().unwrap();
-            let mem_manager = &vm.memory_manager;
-
-            mem_manager.memory
-        };
+        Self::guest_memory(caller).unwrap()
+    }
 
-        memory
+    /// Returns the linear memory of the VM loaded in the host's context.
+    pub(crate) fn guest_memory(caller: &Caller<Self>) -> Result<Memory> {
+        let context = caller.data().0.context.borrow();
+        let vm = context
+            .vm
+            .as_ref()
+            .ok_or_else(|| HostError::NoContext)?
+            .upgrade()
+            .ok_or_else(|| HostError::InternalError(InternalError::CannotUpgradeRc))?;
+
+        Ok(vm.memory_manager.memory)
     }
 
     pub(crate) fn write_to_memory(
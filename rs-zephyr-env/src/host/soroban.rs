@@ -1,16 +1,15 @@
-use super::Host;
+use super::{soroban_error, Host, ZEPHYR_CONTRACT_ID};
 use crate::{
     db::{database::ZephyrDatabase, ledger::LedgerStateRead},
-    error::{HostError, InternalError},
     snapshot::{snapshot_utils, DynamicSnapshot},
     trace::TracePoint,
 };
 use anyhow::Result;
+use rs_zephyr_common::ContractDataEntry;
 use soroban_env_host::{
-    budget::AsBudget,
     xdr::{
-        AccountId, Hash, HostFunction, LedgerEntryData, Limits, PublicKey, ReadXdr, ScAddress,
-        ScVal, Uint256, WriteXdr,
+        AccountId, ContractEvent, ContractEventType, Hash, HostFunction, LedgerEntryData, Limits,
+        PublicKey, ReadXdr, ScAddress, ScVal, Uint256, WriteXdr,
     },
     Env, LedgerInfo, Symbol, TryFromVal, Val,
 };
@@ -51,21 +50,8 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         size: i64,
     ) -> (Caller<Self>, Result<(i64, i64)>) {
         let effect = (|| {
-            let host = caller.data();
-
             let key = {
-                let memory = {
-                    let context = host.0.context.borrow();
-                    let vm = context
-                        .vm
-                        .as_ref()
-                        .ok_or_else(|| HostError::NoContext)?
-                        .upgrade()
-                        .ok_or_else(|| HostError::InternalError(InternalError::CannotUpgradeRc))?;
-                    let mem_manager = &vm.memory_manager;
-
-                    mem_manager.memory
-                };
+                let memory = Self::guest_memory(&caller)?;
 
                 let segment = (offset, size);
 
@@ -96,6 +82,53 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         Self::internal_read_contract_data_entry_by_contract_id_and_key(caller, contract, key)
     }
 
+    pub(crate) fn read_contract_instances(
+        caller: Caller<Self>,
+        offset: i64,
+        size: i64,
+    ) -> (Caller<Self>, Result<(i64, i64)>) {
+        let effect = (|| {
+            let host = caller.data();
+
+            let contracts: Vec<[u8; 32]> = {
+                let memory = Self::guest_memory(&caller)?;
+
+                let segment = (offset, size);
+                bincode::deserialize(&Self::read_segment_from_memory(&memory, &caller, segment)?)?
+            };
+
+            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+                TracePoint::LedgerImpl,
+                format!(
+                    "Reading contract instances for {} contracts.",
+                    contracts.len()
+                ),
+                false,
+            );
+
+            let ledger = &host.0.ledger.0.ledger;
+            let instances: Vec<_> = contracts
+                .into_iter()
+                .map(|contract| {
+                    ledger.read_contract_data_entry_by_contract_id_and_key(
+                        ScAddress::Contract(Hash(contract)),
+                        ScVal::LedgerKeyContractInstance,
+                    )
+                })
+                .collect();
+
+            Ok(bincode::serialize(&instances)?)
+        })();
+
+        let read = if let Ok(read) = effect {
+            read
+        } else {
+            return (caller, Err(effect.err().unwrap()));
+        };
+
+        Self::write_to_memory(caller, read)
+    }
+
     pub(crate) fn read_contract_entries(
         caller: Caller<Self>,
         contract: [u8; 32],
@@ -105,7 +138,51 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         let contract = ScAddress::Contract(Hash(contract));
         let read = {
             let ledger = &host.0.ledger.0.ledger;
-            bincode::serialize(&ledger.read_contract_data_entries_by_contract_id(contract)).unwrap()
+            bincode::serialize(&read_sorted_contract_entries(ledger, contract)).unwrap()
+        };
+
+        Self::write_to_memory(caller, read)
+    }
+
+    pub(crate) fn read_contract_entries_by_prefix(
+        caller: Caller<Self>,
+        contract: [u8; 32],
+        offset: i64,
+        size: i64,
+    ) -> (Caller<Self>, Result<(i64, i64)>) {
+        let effect = (|| {
+            let host = caller.data();
+
+            let prefix = {
+                let memory = Self::guest_memory(&caller)?;
+
+                let segment = (offset, size);
+
+                ScVal::from_xdr(
+                    Self::read_segment_from_memory(&memory, &caller, segment)?,
+                    Limits::none(),
+                )?
+            };
+
+            let prefix = match prefix {
+                ScVal::Vec(Some(elements)) => elements.to_vec(),
+                other => vec![other],
+            };
+
+            let ledger = &host.0.ledger.0.ledger;
+            let entries: Vec<_> =
+                read_sorted_contract_entries(ledger, ScAddress::Contract(Hash(contract)))
+                    .into_iter()
+                    .filter(|entry| key_matches_prefix(&entry.key, &prefix))
+                    .collect();
+
+            Ok(bincode::serialize(&entries)?)
+        })();
+
+        let read = if let Ok(read) = effect {
+            read
+        } else {
+            return (caller, Err(effect.err().unwrap()));
         };
 
         Self::write_to_memory(caller, read)
@@ -126,6 +203,76 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         Self::write_to_memory(caller, read)
     }
 
+    pub(crate) fn emit_event(
+        caller: Caller<Self>,
+        topics: (i64, i64),
+        data: (i64, i64),
+    ) -> Result<()> {
+        let host = caller.data();
+
+        let (topics, data) = {
+            let memory = Self::guest_memory(&caller)?;
+
+            let topics = ScVal::from_xdr(
+                Self::read_segment_from_memory(&memory, &caller, topics)?,
+                Limits::none(),
+            )?;
+            let data = ScVal::from_xdr(
+                Self::read_segment_from_memory(&memory, &caller, data)?,
+                Limits::none(),
+            )?;
+
+            (topics, data)
+        };
+
+        let topics = match topics {
+            ScVal::Vec(Some(topics)) => topics.to_vec(),
+            ScVal::Vec(None) => vec![],
+            topic => vec![topic],
+        };
+
+        caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
+            TracePoint::SorobanEnvironment,
+            format!("Emitting contract event with {} topics.", topics.len()),
+            false,
+        );
+
+        let soroban = host.0.soroban.borrow().to_owned();
+
+        soroban
+            .with_test_contract_frame(
+                Hash(ZEPHYR_CONTRACT_ID),
+                Symbol::from_small_str("test"),
+                || {
+                    let mut topics_obj = soroban.vec_new()?;
+                    for topic in topics.iter() {
+                        let topic = soroban.to_valid_host_val(topic)?;
+                        topics_obj = soroban.vec_push_back(topics_obj, topic)?;
+                    }
+
+                    let data = soroban.to_valid_host_val(&data)?;
+                    Ok(soroban.contract_event(topics_obj, data)?.into())
+                },
+            )
+            .map_err(|error| soroban_error(error.error))?;
+
+        *host.0.soroban.borrow_mut() = soroban;
+
+        Ok(())
+    }
+
+    /// Returns the contract events emitted by the program.
+    pub fn read_events(&self) -> Result<Vec<ContractEvent>> {
+        let events = self.0.soroban.borrow().get_events()?;
+
+        Ok(events
+            .0
+            .into_iter()
+            .filter(|event| !event.failed_call && event.event.type_ == ContractEventType::Contract)
+            .map(|event| event.event)
+            .collect())
+    }
+
     pub(crate) fn scval_to_valid_host_val(
         caller: Caller<Self>,
         scval: ScVal,
@@ -135,16 +282,16 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
 
             let (soroban, val) = {
                 let soroban = host.0.soroban.borrow().to_owned();
-                soroban.as_budget().reset_unlimited().unwrap();
 
                 soroban.enable_debug().unwrap();
 
                 let val = soroban
                     .with_test_contract_frame(
-                        Hash([0; 32]),
+                        Hash(ZEPHYR_CONTRACT_ID),
                         Symbol::from_small_str("test"),
                         || soroban.to_valid_host_val(&scval),
-                    )?
+                    )
+                    .map_err(|error| soroban_error(error.error))?
                     .get_payload() as i64;
 
                 (soroban, val)
@@ -166,7 +313,6 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
 
         let res = {
             let soroban = host.0.soroban.borrow().to_owned();
-            soroban.as_budget().reset_unlimited().unwrap();
             soroban.enable_debug().unwrap();
 
             caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
@@ -175,8 +321,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 false,
             );
 
-            let scval = ScVal::try_from_val(&soroban, &val)
-                .map_err(|e| HostError::SorobanHostWithContext(e));
+            let scval = ScVal::try_from_val(&soroban, &val).map_err(soroban_error);
             let scval = if let Ok(scval) = scval {
                 scval
             } else {
@@ -198,18 +343,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         let resp = (|| {
             let host = caller.data();
             let host_fn = {
-                let memory = {
-                    let context = host.0.context.borrow();
-                    let vm = context
-                        .vm
-                        .as_ref()
-                        .ok_or_else(|| HostError::NoContext)?
-                        .upgrade()
-                        .ok_or_else(|| HostError::InternalError(InternalError::CannotUpgradeRc))?;
-                    let mem_manager = &vm.memory_manager;
-
-                    mem_manager.memory
-                };
+                let memory = Self::guest_memory(&caller)?;
 
                 let segment = (offset, size);
                 let bytes = Self::read_segment_from_memory(&memory, &caller, segment)?;
@@ -217,19 +351,25 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 HostFunction::from_xdr(bytes, Limits::none())?
             };
 
-            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                TracePoint::SorobanEnvironment,
-                format!("Simulating host function {:?}.", host_fn),
-                false,
-            );
+            caller
+                .data()
+                .0
+                .stack_trace
+                .borrow_mut()
+                .maybe_add_payload_trace(
+                    TracePoint::SorobanEnvironment,
+                    &host_fn,
+                    |host_fn| format!("Simulating host function {}.", host_fn),
+                    false,
+                );
 
             let snapshot_source = Rc::new(DynamicSnapshot {});
             let source = AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(source)));
             let mut ledger_info = LedgerInfo::default();
             ledger_info.protocol_version = 21;
-            let ledger_from_state = snapshot_utils::get_current_ledger_sequence();
-            ledger_info.sequence_number = ledger_from_state.0 as u32;
-            ledger_info.timestamp = ledger_from_state.1 as u64;
+            let ledger_from_state = snapshot_utils::get_current_ledger_sequence()?;
+            ledger_info.sequence_number = ledger_from_state.0;
+            ledger_info.timestamp = ledger_from_state.1;
             ledger_info.network_id = host.0.network_id;
             ledger_info.max_entry_ttl = 3110400;
             let bucket_size: u64 = {
@@ -259,11 +399,17 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
                 true,
             )?;
 
-            caller.data().0.stack_trace.borrow_mut().maybe_add_trace(
-                TracePoint::SorobanEnvironment,
-                format!("Simulated with result {:?}.", resp.invoke_result),
-                false,
-            );
+            caller
+                .data()
+                .0
+                .stack_trace
+                .borrow_mut()
+                .maybe_add_payload_trace(
+                    TracePoint::SorobanEnvironment,
+                    &resp.invoke_result,
+                    |result| format!("Simulated with result {}.", result),
+                    false,
+                );
 
             Ok(resp)
         })();
@@ -288,36 +434,40 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
             let contract = ScAddress::Contract(Hash(contract));
             let ledger = &host.0.ledger.0.ledger;
 
-            let data = ledger.read_contract_data_entries_by_contract_id(contract);
+            let data = read_sorted_contract_entries(ledger, contract);
 
             let soroban = host.0.soroban.borrow().to_owned();
-            soroban.as_budget().reset_unlimited().unwrap();
 
             soroban.enable_debug().unwrap();
             //let mut current = soroban.get_ledger_info().unwrap().unwrap_or_default();
             //let map = soroban.map_new().unwrap();
 
             let val = soroban
-                .with_test_contract_frame(Hash([0; 32]), Symbol::from_small_str("test"), || {
-                    let mut map = soroban.map_new()?;
-
-                    for entry in data {
-                        let LedgerEntryData::ContractData(d) = entry.entry.data else {
-                            panic!("invalid xdr")
-                        };
-
-                        if d.key != ScVal::LedgerKeyContractInstance {
-                            let key = soroban.to_valid_host_val(&d.key)?;
-                            let val = soroban.to_valid_host_val(&d.val)?;
-
-                            map = soroban.map_put(map, key, val)?;
+                .with_test_contract_frame(
+                    Hash(ZEPHYR_CONTRACT_ID),
+                    Symbol::from_small_str("test"),
+                    || {
+                        let mut map = soroban.map_new()?;
+
+                        for entry in data {
+                            let LedgerEntryData::ContractData(d) = entry.entry.data else {
+                                panic!("invalid xdr")
+                            };
+
+                            if d.key != ScVal::LedgerKeyContractInstance {
+                                let key = soroban.to_valid_host_val(&d.key)?;
+                                let val = soroban.to_valid_host_val(&d.val)?;
+
+                                map = soroban.map_put(map, key, val)?;
+                            }
                         }
-                    }
 
-                    soroban.enable_debug().unwrap();
+                        soroban.enable_debug().unwrap();
 
-                    Ok(map.into())
-                })?
+                        Ok(map.into())
+                    },
+                )
+                .map_err(|error| soroban_error(error.error))?
                 .get_payload() as i64;
 
             (soroban, val)
@@ -328,3 +478,27 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static> Host<DB
         Ok(val)
     }
 }
+
+/// Reads all entries of a contract sorted by the XDR encoding of their keys, so
+/// that programs observe the same order regardless of the ledger implementation.
+fn read_sorted_contract_entries<L: LedgerStateRead>(
+    ledger: &L,
+    contract: ScAddress,
+) -> Vec<ContractDataEntry> {
+    let mut entries = ledger.read_contract_data_entries_by_contract_id(contract);
+    entries.sort_by_cached_key(|entry| entry.key.to_xdr(Limits::none()).unwrap_or_default());
+
+    entries
+}
+
+/// Returns whether a contract data key starts with the given elements.
+///
+/// Keys of enum-like storage such as `Balance(Address)` are vectors whose first
+/// element is the variant's symbol, so `[Symbol("Balance")]` matches all
+/// balances. Non-vector keys only match a single-element prefix equal to them.
+pub(crate) fn key_matches_prefix(key: &ScVal, prefix: &[ScVal]) -> bool {
+    match key {
+        ScVal::Vec(Some(elements)) => elements.starts_with(prefix),
+        key => prefix.len() == 1 && &prefix[0] == key,
+    }
+}
@@ -11,9 +11,11 @@ pub mod snapshot;
 pub mod budget;
 pub mod db;
 pub mod host;
+pub mod relay;
 mod trace;
 pub mod vm;
 
+pub use trace::{Redaction, StackTrace, TracePoint};
 pub use wasmi::Instance;
 
 mod soroban_host_gen;
@@ -0,0 +1,57 @@
+//! Utilities for implementors relaying the messages that programs
+//! send through the host's transmitter.
+//!
+//! Relayers should never trust the received bytes: a guest built against a
+//! different SDK version may send messages this host cannot decode.
+//!
+//! ### Wire format
+//!
+//! Versioned messages are prefixed by [`RELAY_MAGIC`] and a version byte,
+//! followed by the bincode-serialized [`RelayedMessageRequest`]. Messages sent
+//! by guests built before the header was introduced are plain bincode. Bincode
+//! encodes the enum discriminant as a little-endian u32, so the first byte of
+//! a legacy message is the variant index. Legacy messages are therefore only
+//! told apart from versioned ones while [`RelayedMessageRequest`] has fewer
+//! than [`RELAY_MAGIC`] (90) variants.
+
+use crate::error::RelayError;
+use rs_zephyr_common::RelayedMessageRequest;
+
+/// Magic byte prepended to versioned relayed messages.
+pub const RELAY_MAGIC: u8 = 0x5a;
+
+/// Current version of the relayed messages wire format.
+pub const RELAY_VERSION: u8 = 1;
+
+/// Encodes a message with the current wire format header.
+pub fn encode_message(request: &RelayedMessageRequest) -> Result<Vec<u8>, RelayError> {
+    let payload = bincode::serialize(request).map_err(RelayError::Serialization)?;
+
+    Ok([vec![RELAY_MAGIC, RELAY_VERSION], payload].concat())
+}
+
+/// Encodes a message in the wire format of `original`, i.e. with the header
+/// only if `original` has one. Used when the host rewrites a message, so
+/// that relayers still reading plain bincode can decode it.
+pub fn encode_message_like(
+    original: &[u8],
+    request: &RelayedMessageRequest,
+) -> Result<Vec<u8>, RelayError> {
+    match original {
+        [RELAY_MAGIC, ..] => encode_message(request),
+        _ => bincode::serialize(request).map_err(RelayError::Serialization),
+    }
+}
+
+/// Decodes a message received from the host's transmitter.
+pub fn decode_message(message: &[u8]) -> Result<RelayedMessageRequest, RelayError> {
+    let payload = match message {
+        [RELAY_MAGIC, RELAY_VERSION, payload @ ..] => payload,
+        [RELAY_MAGIC, version, ..] => return Err(RelayError::UnsupportedVersion(*version)),
+        // Legacy un-versioned message, see the module docs for why it can't
+        // start with the magic byte.
+        _ => message,
+    };
+
+    bincode::deserialize(payload).map_err(RelayError::Deserialization)
+}
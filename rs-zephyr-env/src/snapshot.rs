@@ -17,32 +17,61 @@ use stellar_xdr::next::Uint256;
 pub struct DynamicSnapshot {}
 
 pub mod snapshot_utils {
+    use crate::error::HostError;
+    use anyhow::Result;
     use rusqlite::{params, Connection};
     use sha2::{Digest, Sha256};
     use soroban_env_host::xdr::{
         Hash, LedgerEntry, LedgerEntryData, LedgerKey, Limits, ReadXdr, WriteXdr,
     };
 
-    pub fn get_current_ledger_sequence() -> (i32, i64) {
-        let conn = Connection::open("/tmp/rs_ingestion_temp/stellar.db").unwrap();
+    /// Returns the sequence and close time of the latest ledger in the
+    /// ledger database.
+    ///
+    /// Errors with [`HostError::NoCurrentLedger`] when the database holds no
+    /// ledger headers, for instance when no ledger is running.
+    pub fn get_current_ledger_sequence() -> Result<(u32, u64)> {
+        Ok(try_current_ledger_sequence()?.ok_or_else(|| HostError::NoCurrentLedger)?)
+    }
+
+    /// Returns the sequence and close time of the latest ledger in the
+    /// ledger database, or `None` when no ledger is running yet.
+    ///
+    /// Drivers such as catchups should use this to tell "no tip yet" apart
+    /// from failures reading the database.
+    pub fn try_current_ledger_sequence() -> Result<Option<(u32, u64)>> {
+        let conn = Connection::open("/tmp/rs_ingestion_temp/stellar.db")?;
         let query_string = format!(
             "SELECT ledgerseq, closetime FROM ledgerheaders ORDER BY ledgerseq DESC LIMIT 1"
         );
 
-        let mut stmt = conn.prepare(&query_string).unwrap();
-        let mut entries = stmt.query(params![]).unwrap();
+        let mut stmt = conn.prepare(&query_string)?;
+        let mut entries = stmt.query(params![])?;
 
-        let row = entries.next().unwrap();
+        let row = if let Some(row) = entries.next()? {
+            row
+        } else {
+            return Ok(None);
+        };
+        let sequence: i64 = row.get(0)?;
+        let close_time: i64 = row.get(1)?;
 
-        if row.is_none() {
-            // Unrecoverable: no ledger is running
-            return (0, 0);
-        }
+        Ok(Some((
+            ledger_sequence(sequence)?,
+            ledger_close_time(close_time)?,
+        )))
+    }
+
+    /// Converts a ledger sequence read from a signed column to the `u32`
+    /// used for ledger sequences across Zephyr, without truncating it.
+    pub fn ledger_sequence(raw: i64) -> Result<u32> {
+        Ok(u32::try_from(raw).map_err(|_| HostError::InvalidLedgerSequence(raw))?)
+    }
 
-        (
-            row.unwrap().get(0).unwrap_or(0),
-            row.unwrap().get(1).unwrap_or(0),
-        )
+    /// Converts a ledger close time read from a signed column to a `u64`
+    /// timestamp, rejecting negative values rather than wrapping them.
+    pub fn ledger_close_time(raw: i64) -> Result<u64> {
+        Ok(u64::try_from(raw).map_err(|_| HostError::InvalidCloseTime(raw))?)
     }
 
     pub fn get_ttl(key: LedgerKey) -> u32 {
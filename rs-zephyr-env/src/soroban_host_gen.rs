@@ -2,7 +2,7 @@
 
 use crate::{
     db::{database::ZephyrDatabase, ledger::LedgerStateRead},
-    host::{FunctionInfo, Host, SorobanTempFunctionInfo},
+    host::{FunctionInfo, Host, HostFunc},
 };
 use soroban_env_host::wasmi::{
     self as soroban_wasmi,
@@ -22,8 +22,6 @@ use soroban_env_host::{
     CheckedEnvArg, Host as SorobanHost, VmCallerEnv,
 };
 
-use wasmi::{Func, Store};
-
 pub(crate) fn build_u32val(host: &soroban_env_host::Host, int: i64) -> Result<U32Val, HostError> {
     U32Val::check_env_arg(
         U32Val::try_marshal_from_relative_value(soroban_wasmi::Value::I64(int), &host).map_err(
@@ -356,10 +354,10 @@ call_macro_with_all_host_functions! { generate_dispatch_functions }
 
 macro_rules! host_function_info_helper {
     {$mod_str:literal, $fn_id:literal, $args:tt, $func_id:ident } => {
-        SorobanTempFunctionInfo {
+        FunctionInfo {
             module: $mod_str,
             func: $fn_id,
-            wrapped: |store| Func::wrap(store, $func_id),
+            wrapped: HostFunc::new($func_id),
         }
     };
 }
@@ -408,8 +406,8 @@ macro_rules! generate_host_function_infos {
         //      look up the numbered function the guest is requesting in this array and
         //      call its associated dispatch function.
 
-        pub(crate) fn get_all_host_functions<DB, L>() -> Vec<SorobanTempFunctionInfo<DB, L>> where DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static {
-            let mut fns: Vec<SorobanTempFunctionInfo<DB, L>> = Vec::new();
+        pub(crate) fn get_all_host_functions<DB, L>() -> Vec<FunctionInfo<DB, L>> where DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static {
+            let mut fns: Vec<FunctionInfo<DB, L>> = Vec::new();
 
             for f in [
                 $(
@@ -438,22 +436,11 @@ macro_rules! generate_host_function_infos {
 
 call_macro_with_all_host_functions! { generate_host_function_infos }
 
-pub fn generate_host_fn_infos<DB, L>(store: &mut Store<Host<DB, L>>) -> Vec<FunctionInfo>
+pub fn generate_host_fn_infos<DB, L>() -> Vec<FunctionInfo<DB, L>>
 where
     DB: ZephyrDatabase + Clone + 'static,
     L: LedgerStateRead + 'static,
 {
     // Here we invoke the x-macro passing generate_host_function_infos as its callback macro.
-    let store = store;
-
-    let functions = get_all_host_functions::<DB, L>()
-        .iter()
-        .map(|temp| FunctionInfo {
-            module: temp.module,
-            func: temp.func,
-            wrapped: (temp.wrapped)(store),
-        })
-        .collect();
-
-    functions
+    get_all_host_functions::<DB, L>()
 }
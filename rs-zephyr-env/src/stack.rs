@@ -6,12 +6,42 @@ use crate::{error::HostError, ZephyrStandard};
 use anyhow::Result;
 use std::{borrow::BorrowMut, cell::RefCell, rc::Rc};
 
+/// Value pushed first by guests using the tagged stack protocol.
+///
+/// Legacy instructions start with the table name symbol, whose lowest byte is
+/// always the symbol tag (14), so the marker can't be mistaken for one.
+pub const TAGGED_PROTOCOL_MARKER: i64 = 0x5a45_5048_5952_0000;
+
+/// Tags that open each section of tagged stack instructions.
+///
+/// The discriminants are part of the host-guest protocol and must never
+/// be changed or reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i64)]
+pub enum StackSection {
+    /// Table name symbol.
+    TableName = 1,
+
+    /// Column count followed by the column name symbols.
+    Columns = 2,
+
+    /// Segment count followed by the (offset, size) pairs of the data.
+    Segments = 3,
+
+    /// Condition count followed by the (column, operator) pairs.
+    Conditions = 4,
+
+    /// Segment count followed by the (offset, size) pairs of the condition arguments.
+    ConditionArgs = 5,
+}
+
 /// Stack implementation.
 #[derive(Clone)]
 pub struct StackImpl {
     /// Inner stack vector.
     pub inner: RefCell<Vec<i64>>,
     step: RefCell<usize>,
+    tagged: RefCell<bool>,
 }
 
 /// Stack implementation wrapper.
@@ -23,6 +53,7 @@ impl ZephyrStandard for StackImpl {
         Ok(Self {
             inner: RefCell::new(Vec::new()),
             step: RefCell::new(0),
+            tagged: RefCell::new(false),
         })
     }
 }
@@ -38,6 +69,7 @@ impl StackImpl {
     pub fn clear(&self) {
         let mut stack = self.inner.borrow_mut();
         *self.step.borrow_mut() = 0;
+        *self.tagged.borrow_mut() = false;
         stack.borrow_mut().clear();
     }
 
@@ -68,6 +100,53 @@ impl StackImpl {
     pub fn get_current_step(&self) -> usize {
         self.step.clone().into_inner()
     }
+
+    /// Detects whether the instructions on the stack use the tagged protocol,
+    /// consuming the protocol marker if present. Must be called before reading
+    /// the first section.
+    pub fn detect_protocol(&self) {
+        let current = self.get_current_step();
+        let tagged = self.inner.borrow().get(current) == Some(&TAGGED_PROTOCOL_MARKER);
+
+        if tagged {
+            *self.step.borrow_mut() = current + 1;
+        }
+
+        *self.tagged.borrow_mut() = tagged;
+    }
+
+    /// Reads the tag opening the next section and checks that it is the expected one.
+    /// This is a no-op for legacy instructions, which carry no tags.
+    pub fn expect_section(&self, section: StackSection) -> Result<(), HostError> {
+        if !*self.tagged.borrow() {
+            return Ok(());
+        }
+
+        if self.get_with_step()? != section as i64 {
+            return Err(HostError::MalformedInstructions);
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the optional section is present, consuming its tag if so.
+    /// Legacy instructions carry no tags, so the section is assumed to be present
+    /// when the stack has not been fully read.
+    pub fn has_section(&self, section: StackSection) -> bool {
+        let current = self.get_current_step();
+        let next = self.inner.borrow().get(current).copied();
+
+        if !*self.tagged.borrow() {
+            return next.is_some();
+        }
+
+        if next == Some(section as i64) {
+            *self.step.borrow_mut() = current + 1;
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl ZephyrStandard for Stack {
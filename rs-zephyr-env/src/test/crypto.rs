@@ -0,0 +1,79 @@
+use super::wasm;
+use crate::{
+    error::HostError,
+    host::{crypto::verify_ed25519_signature, Host},
+    testutils::database::{LedgerReader, MercuryDatabase},
+    vm::Vm,
+    ZephyrMock,
+};
+use std::rc::Rc;
+
+// Hashes "abc" and concludes with the digest.
+const SHA256_MODULE: &str = r#"
+(module
+  (import "env" "host_sha256" (func $sha256 (param i64 i64) (result i64 i64 i64)))
+  (import "env" "conclude_binary" (func $conclude (param i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "abc")
+  (func (export "hash") (local $offset i64) (local $size i64)
+    (call $sha256 (i64.const 0) (i64.const 3))
+    (local.set $size)
+    (local.set $offset)
+    (drop)
+    (drop (call $conclude (local.get $offset) (local.get $size)))))
+"#;
+
+// RFC 8032, section 7.1, test 1: the signature of an empty message.
+const RFC8032_PUBLIC_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+const RFC8032_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+fn rfc8032_vector() -> ([u8; 32], Vec<u8>) {
+    let public_key = hex::decode(RFC8032_PUBLIC_KEY).unwrap().try_into().unwrap();
+    let signature = hex::decode(RFC8032_SIGNATURE).unwrap();
+
+    (public_key, signature)
+}
+
+#[test]
+fn sha256_digest() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &wasm(SHA256_MODULE)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    vm.metered_function_call(&host, "hash").unwrap();
+
+    assert_eq!(
+        hex::encode(host.read_result_bytes().unwrap()),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn verifies_rfc8032_signature() {
+    let (public_key, signature) = rfc8032_vector();
+
+    assert!(verify_ed25519_signature(&public_key, b"", &signature).unwrap());
+}
+
+#[test]
+fn rejects_tampered_signature() {
+    let (public_key, mut signature) = rfc8032_vector();
+
+    assert!(!verify_ed25519_signature(&public_key, b"tampered", &signature).unwrap());
+
+    signature[0] ^= 1;
+    assert!(!verify_ed25519_signature(&public_key, b"", &signature).unwrap());
+}
+
+#[test]
+fn rejects_malformed_signature() {
+    let (public_key, signature) = rfc8032_vector();
+
+    let error = verify_ed25519_signature(&public_key, b"", &signature[..63])
+        .err()
+        .unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::InvalidEd25519Signature)
+    ));
+}
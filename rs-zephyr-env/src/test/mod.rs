@@ -1,2 +1,40 @@
+mod crypto;
 mod database;
+mod relay;
+mod snapshot;
 mod soroban;
+mod trace;
+mod vm;
+
+/// Compiles a test module written in the WebAssembly text format.
+pub(crate) fn wasm(wat: &str) -> Vec<u8> {
+    wat::parse_str(wat).unwrap()
+}
+
+/// Escapes bytes for a data segment of a module in the WebAssembly text format.
+pub(crate) fn wat_data(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("\\{:02x}", byte)).collect()
+}
+
+/// Compiles a module whose `call` export passes `data` to the imported host
+/// function `name` and concludes with the binary the host function returns.
+pub(crate) fn segment_call_module(name: &str, data: &[u8]) -> Vec<u8> {
+    wasm(&format!(
+        r#"
+(module
+  (import "env" "{}" (func $call (param i64 i64) (result i64 i64 i64)))
+  (import "env" "conclude_binary" (func $conclude (param i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{}")
+  (func (export "call") (local $offset i64) (local $size i64)
+    (call $call (i64.const 0) (i64.const {}))
+    (local.set $size)
+    (local.set $offset)
+    (drop)
+    (drop (call $conclude (local.get $offset) (local.get $size)))))
+"#,
+        name,
+        wat_data(data),
+        data.len()
+    ))
+}
@@ -0,0 +1,303 @@
+use crate::{
+    error::RelayError,
+    relay::{decode_message, RELAY_MAGIC, RELAY_VERSION},
+};
+use rs_zephyr_common::{
+    http::{AgnosticRequest, Method},
+    log::{LogLevel, ZephyrLog},
+    RelayedMessageRequest,
+};
+
+#[test]
+fn rejects_unsupported_version() {
+    let decoded = decode_message(&[RELAY_MAGIC, RELAY_VERSION + 1, 0, 0, 0, 0]);
+
+    assert!(matches!(
+        decoded,
+        Err(RelayError::UnsupportedVersion(version)) if version == RELAY_VERSION + 1
+    ));
+}
+
+#[test]
+fn rejects_truncated_message() {
+    assert!(matches!(
+        decode_message(&[RELAY_MAGIC, RELAY_VERSION]),
+        Err(RelayError::Deserialization(_))
+    ));
+    assert!(matches!(
+        decode_message(&[]),
+        Err(RelayError::Deserialization(_))
+    ));
+}
+
+#[test]
+fn decodes_legacy_messages_of_every_variant() {
+    let requests = [
+        RelayedMessageRequest::Http(AgnosticRequest {
+            url: "https://example.com".into(),
+            method: Method::Post,
+            body: Some("{}".into()),
+            headers: vec![],
+        }),
+        RelayedMessageRequest::Log(ZephyrLog {
+            level: LogLevel::Debug,
+            message: "hello".into(),
+            data: None,
+        }),
+    ];
+
+    for request in requests {
+        // Adding a variant breaks this match so that it gets covered here too.
+        match &request {
+            RelayedMessageRequest::Http(_) | RelayedMessageRequest::Log(_) => {}
+        }
+
+        let legacy = bincode::serialize(&request).unwrap();
+        assert_ne!(legacy[0], RELAY_MAGIC);
+
+        let decoded = decode_message(&legacy).unwrap();
+        assert_eq!(bincode::serialize(&decoded).unwrap(), legacy);
+    }
+}
+
+/// Compiles a module whose `send` export, also exported as the standard
+/// `on_close` entry point, relays `message` `times` times.
+fn send_module(message: &[u8], times: usize) -> Vec<u8> {
+    let send = format!(
+        "(drop (call $send (i64.const 0) (i64.const {})))",
+        message.len()
+    );
+
+    super::wasm(&format!(
+        r#"
+(module
+  (import "env" "tx_send_message" (func $send (param i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{}")
+  (func (export "send") (export "on_close") {}))
+"#,
+        super::wat_data(message),
+        send.repeat(times)
+    ))
+}
+
+fn http_request() -> Vec<u8> {
+    crate::relay::encode_message(&RelayedMessageRequest::Http(AgnosticRequest {
+        url: "https://example.com".into(),
+        method: Method::Get,
+        body: None,
+        headers: vec![],
+    }))
+    .unwrap()
+}
+
+#[test]
+fn caps_http_requests() {
+    use crate::{
+        host::Host,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use std::rc::Rc;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_max_http_requests(2);
+    host.add_transmitter(tx);
+    host.as_stack_trace_mut().enable();
+    let vm = Vm::new(&host, &send_module(&http_request(), 3)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    // The quota applies to each invocation.
+    for _ in 0..2 {
+        vm.metered_function_call(&host, "send").unwrap();
+
+        let mut received = 0;
+        while rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 2);
+    }
+
+    assert!(host
+        .read_stack_trace()
+        .to_string()
+        .contains("Program exceeded the quota of 2 HTTP requests."));
+}
+
+#[test]
+fn failed_http_requests_are_not_counted() {
+    use crate::{
+        host::Host,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use std::rc::Rc;
+
+    // Without a transmitter no request can be sent.
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_max_http_requests(1);
+    host.as_stack_trace_mut().enable();
+    let vm = Vm::new(&host, &send_module(&http_request(), 2)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    vm.metered_function_call(&host, "send").unwrap();
+
+    let trace = host.read_stack_trace().to_string();
+    assert_eq!(
+        trace
+            .matches("Couldn't find transmitter in virtual machine.")
+            .count(),
+        2
+    );
+    assert!(!trace.contains("exceeded the quota"));
+}
+
+#[test]
+fn repeated_logs_are_summarized() {
+    use crate::{
+        host::Host,
+        relay::encode_message,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use std::rc::Rc;
+
+    let log = encode_message(&RelayedMessageRequest::Log(ZephyrLog {
+        level: LogLevel::Debug,
+        message: "hello".into(),
+        data: None,
+    }))
+    .unwrap();
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_log_dedup(true);
+    host.add_transmitter(tx);
+    let vm = Vm::new(&host, &send_module(&log, 3)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    // Both entry points relay the summary of the last repeated log when
+    // the program returns.
+    let entry_points: [&dyn Fn() -> anyhow::Result<()>; 2] = [
+        &|| vm.metered_call(&host),
+        &|| vm.metered_function_call(&host, "send").map(|_| ()),
+    ];
+    for invoke in entry_points {
+        invoke().unwrap();
+
+        let mut messages = Vec::new();
+        while let Ok(message) = rx.try_recv() {
+            match decode_message(&message).unwrap() {
+                RelayedMessageRequest::Log(log) => messages.push(log.message),
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(messages, vec!["hello", "hello (repeated 2 times)"]);
+    }
+}
+
+#[test]
+fn redacted_logs_still_decode() {
+    use crate::{
+        host::Host,
+        relay::encode_message,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        Redaction, ZephyrMock,
+    };
+    use std::rc::Rc;
+
+    let data = vec![7; 32];
+    let log = encode_message(&RelayedMessageRequest::Log(ZephyrLog {
+        level: LogLevel::Debug,
+        message: "a message that is longer than the redaction limit".into(),
+        data: Some(data.clone()),
+    }))
+    .unwrap();
+
+    for (redaction, expected_len) in [(Redaction::Truncate(4), 4), (Redaction::Hash(4), 16)] {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+        host.set_redaction(redaction);
+        host.add_transmitter(tx);
+        let vm = Vm::new(&host, &send_module(&log, 1)).unwrap();
+        host.load_context(Rc::downgrade(&vm)).unwrap();
+        vm.metered_function_call(&host, "send").unwrap();
+
+        match decode_message(&rx.try_recv().unwrap()).unwrap() {
+            RelayedMessageRequest::Log(log) => {
+                // Only the data is redacted, the message is relayed as is.
+                assert_eq!(
+                    log.message,
+                    "a message that is longer than the redaction limit"
+                );
+                let redacted = log.data.unwrap();
+                assert_eq!(redacted.len(), expected_len);
+                if redaction == Redaction::Truncate(4) {
+                    assert_eq!(redacted, data[..4]);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[tokio::test]
+async fn relayed_messages_are_returned() {
+    use crate::{relay::encode_message, testutils::relay_messages};
+
+    let log = RelayedMessageRequest::Log(ZephyrLog {
+        level: LogLevel::Debug,
+        message: "hello".into(),
+        data: None,
+    });
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    tx.send(vec![RELAY_MAGIC, RELAY_VERSION + 1]).unwrap();
+    tx.send(encode_message(&log).unwrap()).unwrap();
+    drop(tx);
+
+    let relayed = relay_messages(rx, true).await;
+    assert_eq!(relayed.logs.len(), 1);
+    assert_eq!(relayed.logs[0].message, "hello");
+    assert!(relayed.http_responses.is_empty());
+    assert!(matches!(
+        relayed.skipped.as_slice(),
+        [RelayError::UnsupportedVersion(version)] if *version == RELAY_VERSION + 1
+    ));
+}
+
+#[tokio::test]
+async fn invalid_http_headers_are_recorded() {
+    use crate::{error::HttpRelayError, relay::encode_message, testutils::relay_messages};
+
+    let request = |name: &str, value: &str| {
+        encode_message(&RelayedMessageRequest::Http(AgnosticRequest {
+            url: "https://example.com".into(),
+            method: Method::Get,
+            body: None,
+            headers: vec![(name.into(), value.into())],
+        }))
+        .unwrap()
+    };
+
+    for deterministic in [true, false] {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tx.send(request("invalid name", "value")).unwrap();
+        tx.send(request("name", "invalid\nvalue")).unwrap();
+        drop(tx);
+
+        let relayed = relay_messages(rx, deterministic).await;
+        assert!(matches!(
+            relayed.http_responses.as_slice(),
+            [
+                Err(HttpRelayError::InvalidHeader(first)),
+                Err(HttpRelayError::InvalidHeader(second)),
+            ] if first == "invalid name" && second == "name"
+        ));
+    }
+}
@@ -0,0 +1,30 @@
+use crate::{
+    error::HostError,
+    snapshot::snapshot_utils::{ledger_close_time, ledger_sequence},
+};
+
+#[test]
+fn ledger_sequence_boundaries() {
+    assert_eq!(ledger_sequence(0).unwrap(), 0);
+    assert_eq!(ledger_sequence(u32::MAX as i64).unwrap(), u32::MAX);
+
+    for raw in [-1, u32::MAX as i64 + 1] {
+        let error = ledger_sequence(raw).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<HostError>(),
+            Some(HostError::InvalidLedgerSequence(value)) if *value == raw
+        ));
+    }
+}
+
+#[test]
+fn ledger_close_time_boundaries() {
+    assert_eq!(ledger_close_time(0).unwrap(), 0);
+    assert_eq!(ledger_close_time(i64::MAX).unwrap(), i64::MAX as u64);
+
+    let error = ledger_close_time(-1).err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::InvalidCloseTime(-1))
+    ));
+}
@@ -10,3 +10,356 @@ async fn soroban_host() {
     let invocation = invocation.unwrap();
     assert!(invocation.is_ok());
 }
+
+#[test]
+fn key_prefix_matching() {
+    use crate::host::soroban::key_matches_prefix;
+    use soroban_env_host::xdr::{ScSymbol, ScVal};
+
+    let symbol = |s: &str| ScVal::Symbol(ScSymbol(s.try_into().unwrap()));
+    let balance = ScVal::Vec(Some(
+        vec![symbol("Balance"), ScVal::U32(1)].try_into().unwrap(),
+    ));
+
+    assert!(key_matches_prefix(&balance, &[symbol("Balance")]));
+    assert!(key_matches_prefix(
+        &balance,
+        &[symbol("Balance"), ScVal::U32(1)]
+    ));
+    assert!(!key_matches_prefix(&balance, &[symbol("Allowance")]));
+    assert!(key_matches_prefix(&symbol("Admin"), &[symbol("Admin")]));
+    assert!(!key_matches_prefix(
+        &symbol("Admin"),
+        &[symbol("Admin"), ScVal::U32(1)]
+    ));
+}
+
+/// Module converting the given XDR-encoded `ScVal` with `scval_to_valid_host_val`,
+/// once with `convert` and ten times with `convert_many`.
+fn conversion_module(xdr: &[u8]) -> Vec<u8> {
+    let data = super::wat_data(xdr);
+
+    super::wasm(&format!(
+        r#"
+(module
+  (import "env" "scval_to_valid_host_val" (func (param i64 i64) (result i64 i64)))
+  (memory (export "memory") 1)
+  (func $convert i64.const 0 i64.const {} call 0 drop drop)
+  (func (export "convert") call $convert)
+  (func (export "convert_many") (local $i i32)
+    (loop $again
+      call $convert
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $again (i32.lt_u (local.get $i) (i32.const 10)))))
+  (data (i32.const 0) "{}"))
+"#,
+        xdr.len(),
+        data
+    ))
+}
+
+#[test]
+fn soroban_budget_applies_to_conversions() {
+    use crate::{
+        error::HostError,
+        host::Host,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use soroban_env_host::xdr::{Limits, ScVal, WriteXdr};
+    use std::rc::Rc;
+
+    let values: Vec<ScVal> = (0..100).map(ScVal::U32).collect();
+    let xdr = ScVal::Vec(Some(values.try_into().unwrap()))
+        .to_xdr(Limits::none())
+        .unwrap();
+    let module = conversion_module(&xdr);
+
+    let convert = |budget: Option<(u64, u64)>| {
+        let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+        host.as_stack_trace_mut().enable();
+        if let Some((cpu_insns, mem_bytes)) = budget {
+            host.set_soroban_budget(cpu_insns, mem_bytes).unwrap();
+        }
+
+        let vm = Vm::new(&host, &module).unwrap();
+        host.load_context(Rc::downgrade(&vm)).unwrap();
+        let result = vm.metered_function_call(&host, "convert");
+
+        (result, host.read_stack_trace().to_string())
+    };
+
+    let (result, trace) = convert(None);
+    assert!(result.is_ok());
+    assert!(!trace.contains("Hit error"));
+
+    let (result, trace) = convert(Some((1_000, 100)));
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<HostError>(),
+        Some(HostError::SorobanBudgetExceeded)
+    ));
+    assert!(trace.contains("Hit error"));
+}
+
+#[test]
+fn soroban_budget_is_shared_by_the_conversions_of_an_invocation() {
+    use crate::{
+        error::HostError,
+        host::Host,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use soroban_env_host::{
+        budget::AsBudget,
+        xdr::{Limits, ScVal, WriteXdr},
+    };
+    use std::rc::Rc;
+
+    let values: Vec<ScVal> = (0..10).map(ScVal::U32).collect();
+    let xdr = ScVal::Vec(Some(values.try_into().unwrap()))
+        .to_xdr(Limits::none())
+        .unwrap();
+    let module = conversion_module(&xdr);
+
+    let invoke = |host: &Host<MercuryDatabase, LedgerReader>, fname: &str| {
+        let vm = Vm::new(host, &module).unwrap();
+        host.load_context(Rc::downgrade(&vm)).unwrap();
+        vm.metered_function_call(host, fname)
+    };
+
+    // Measure the cost of a single conversion.
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    invoke(&host, "convert").unwrap();
+    let (cpu_insns, mem_bytes) = {
+        let soroban = host.0.soroban.borrow();
+        let budget = soroban.as_budget();
+        (
+            budget.get_cpu_insns_consumed().unwrap(),
+            budget.get_mem_bytes_consumed().unwrap(),
+        )
+    };
+
+    // Every conversion fits the budget, but ten of them don't.
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_soroban_budget(cpu_insns * 3, mem_bytes * 3)
+        .unwrap();
+
+    assert!(invoke(&host, "convert").is_ok());
+    // The budget is reapplied to every invocation.
+    assert!(invoke(&host, "convert").is_ok());
+
+    let error = invoke(&host, "convert_many").unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::SorobanBudgetExceeded)
+    ));
+}
+
+/// Module emitting an event with the given XDR-encoded topics and data.
+fn event_module(topics: &[u8], data: &[u8]) -> Vec<u8> {
+    super::wasm(&format!(
+        r#"
+(module
+  (import "env" "emit_event" (func $emit (param i64 i64 i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{}{}")
+  (func (export "emit")
+    (drop (call $emit (i64.const 0) (i64.const {}) (i64.const {}) (i64.const {})))))
+"#,
+        super::wat_data(topics),
+        super::wat_data(data),
+        topics.len(),
+        topics.len(),
+        data.len()
+    ))
+}
+
+#[test]
+fn emitted_events_are_read_back() {
+    use crate::{
+        host::Host,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use soroban_env_host::xdr::{ContractEventBody, Limits, ScSymbol, ScVal, WriteXdr};
+    use std::rc::Rc;
+
+    let topic = ScVal::Symbol(ScSymbol("transfer".try_into().unwrap()));
+    let topics = ScVal::Vec(Some(vec![topic.clone()].try_into().unwrap()));
+    let module = event_module(
+        &topics.to_xdr(Limits::none()).unwrap(),
+        &ScVal::U32(7).to_xdr(Limits::none()).unwrap(),
+    );
+
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &module).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+    vm.metered_function_call(&host, "emit").unwrap();
+
+    let events = host.read_events().unwrap();
+    assert_eq!(events.len(), 1);
+    let ContractEventBody::V0(body) = &events[0].body;
+    assert_eq!(body.topics.to_vec(), vec![topic]);
+    assert_eq!(body.data, ScVal::U32(7));
+}
+
+#[test]
+fn events_are_emitted_as_the_contract_address() {
+    use crate::{
+        host::Host,
+        testutils::database::{LedgerReader, MercuryDatabase},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use soroban_env_host::xdr::{Limits, ScAddress, ScVal, WriteXdr};
+    use std::rc::Rc;
+
+    let module = event_module(
+        &ScVal::Vec(None).to_xdr(Limits::none()).unwrap(),
+        &ScVal::Void.to_xdr(Limits::none()).unwrap(),
+    );
+
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &module).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+    vm.metered_function_call(&host, "emit").unwrap();
+
+    let events = host.read_events().unwrap();
+    assert_eq!(
+        events[0].contract_id.clone().map(ScAddress::Contract),
+        Some(host.contract_address())
+    );
+}
+
+/// Contract data entry of `contract` as returned by the ledger.
+fn contract_entry(
+    contract: [u8; 32],
+    key: soroban_env_host::xdr::ScVal,
+    val: soroban_env_host::xdr::ScVal,
+) -> rs_zephyr_common::ContractDataEntry {
+    use soroban_env_host::xdr::{
+        ContractDataDurability, ContractDataEntry, ExtensionPoint, Hash, LedgerEntry,
+        LedgerEntryData, LedgerEntryExt, ScAddress,
+    };
+
+    let contract_id = ScAddress::Contract(Hash(contract));
+    rs_zephyr_common::ContractDataEntry {
+        contract_id: contract_id.clone(),
+        key: key.clone(),
+        entry: LedgerEntry {
+            last_modified_ledger_seq: 0,
+            data: LedgerEntryData::ContractData(ContractDataEntry {
+                ext: ExtensionPoint::V0,
+                contract: contract_id,
+                key,
+                durability: ContractDataDurability::Persistent,
+                val,
+            }),
+            ext: LedgerEntryExt::V0,
+        },
+        durability: ContractDataDurability::Persistent as i32,
+        last_modified: 0,
+    }
+}
+
+#[test]
+fn reads_contract_instances() {
+    use crate::{
+        host::Host,
+        testutils::{RecordingDatabase, SeededLedger},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use rs_zephyr_common::ContractDataEntry;
+    use soroban_env_host::xdr::{Hash, ScAddress, ScVal};
+    use std::rc::Rc;
+
+    let contracts = vec![[1u8; 32], [2u8; 32]];
+    let module = super::segment_call_module(
+        "read_contract_instances",
+        &bincode::serialize(&contracts).unwrap(),
+    );
+
+    let host: Host<RecordingDatabase, SeededLedger> = Host::mocked().unwrap();
+    // Only the first contract has an instance.
+    host.0.ledger.0.ledger.set_entries(vec![
+        contract_entry([1; 32], ScVal::LedgerKeyContractInstance, ScVal::Void),
+        contract_entry([1; 32], ScVal::U32(0), ScVal::Void),
+        contract_entry([2; 32], ScVal::U32(0), ScVal::Void),
+    ]);
+    let vm = Vm::new(&host, &module).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+    vm.metered_function_call(&host, "call").unwrap();
+
+    let instances: Vec<Option<ContractDataEntry>> =
+        bincode::deserialize(&host.read_result_bytes().unwrap()).unwrap();
+    assert_eq!(instances.len(), 2);
+    let instance = instances[0].as_ref().unwrap();
+    assert_eq!(instance.contract_id, ScAddress::Contract(Hash([1; 32])));
+    assert_eq!(instance.key, ScVal::LedgerKeyContractInstance);
+    assert!(instances[1].is_none());
+}
+
+#[test]
+fn reads_contract_entries_by_prefix() {
+    use crate::{
+        host::Host,
+        testutils::{RecordingDatabase, SeededLedger},
+        vm::Vm,
+        ZephyrMock,
+    };
+    use rs_zephyr_common::ContractDataEntry;
+    use soroban_env_host::xdr::{Limits, ScSymbol, ScVal, WriteXdr};
+    use std::rc::Rc;
+
+    let symbol = |s: &str| ScVal::Symbol(ScSymbol(s.try_into().unwrap()));
+    let key = |variant: &str, index: u32| {
+        ScVal::Vec(Some(
+            vec![symbol(variant), ScVal::U32(index)].try_into().unwrap(),
+        ))
+    };
+
+    let prefix = ScVal::Vec(Some(vec![symbol("Balance")].try_into().unwrap()))
+        .to_xdr(Limits::none())
+        .unwrap();
+    // The contract is passed as four zeroed parts.
+    let module = super::wasm(&format!(
+        r#"
+(module
+  (import "env" "read_contract_entries_by_contract_and_prefix"
+    (func $read (param i64 i64 i64 i64 i64 i64) (result i64 i64 i64)))
+  (import "env" "conclude_binary" (func $conclude (param i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{}")
+  (func (export "read") (local $offset i64) (local $size i64)
+    (call $read (i64.const 0) (i64.const 0) (i64.const 0) (i64.const 0)
+      (i64.const 0) (i64.const {}))
+    (local.set $size)
+    (local.set $offset)
+    (drop)
+    (drop (call $conclude (local.get $offset) (local.get $size)))))
+"#,
+        super::wat_data(&prefix),
+        prefix.len()
+    ));
+
+    let host: Host<RecordingDatabase, SeededLedger> = Host::mocked().unwrap();
+    host.0.ledger.0.ledger.set_entries(vec![
+        contract_entry([0; 32], key("Balance", 2), ScVal::Void),
+        contract_entry([0; 32], key("Allowance", 1), ScVal::Void),
+        contract_entry([0; 32], key("Balance", 1), ScVal::Void),
+        contract_entry([0; 32], symbol("Admin"), ScVal::Void),
+        contract_entry([1; 32], key("Balance", 3), ScVal::Void),
+    ]);
+    let vm = Vm::new(&host, &module).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+    vm.metered_function_call(&host, "read").unwrap();
+
+    let entries: Vec<ContractDataEntry> =
+        bincode::deserialize(&host.read_result_bytes().unwrap()).unwrap();
+    let keys: Vec<ScVal> = entries.into_iter().map(|entry| entry.key).collect();
+    assert_eq!(keys, vec![key("Balance", 1), key("Balance", 2)]);
+}
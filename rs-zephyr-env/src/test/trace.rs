@@ -0,0 +1,83 @@
+use crate::{Redaction, StackTrace, TracePoint};
+
+#[test]
+fn stack_trace_json_roundtrip() {
+    let mut trace = StackTrace::default();
+    assert_eq!(trace.to_json().unwrap(), "null");
+
+    trace.enable();
+    trace.maybe_add_trace(TracePoint::DatabaseImpl, "Reading rows.", false);
+    trace.maybe_add_trace(TracePoint::ZephyrEnvironment, "Out of fuel.", true);
+
+    let json = trace.to_json().unwrap();
+    let decoded = StackTrace::from_json(&json).unwrap();
+    assert_eq!(decoded.to_string(), trace.to_string());
+}
+
+#[test]
+fn redacts_long_payloads() {
+    let payload = b"Bearer eyJhbGciOiJIUzI1NiJ9".to_vec();
+
+    assert_eq!(Redaction::Disabled.redact_bytes(payload.clone()), payload);
+    assert_eq!(
+        Redaction::Truncate(6).redact_bytes(payload.clone()),
+        b"Bearer"
+    );
+    assert_eq!(Redaction::Hash(6).redact_bytes(payload.clone()).len(), 16);
+    assert_eq!(Redaction::Hash(64).redact_bytes(payload.clone()), payload);
+
+    let mut trace = StackTrace::default();
+    trace.enable();
+    trace.set_redaction(Redaction::Truncate(3));
+    trace.maybe_add_payload_trace(
+        TracePoint::ZephyrEnvironment,
+        &vec![1, 2, 3],
+        |bytes| format!("Reading bytes {}.", bytes),
+        false,
+    );
+    trace.maybe_add_trace(
+        TracePoint::ZephyrEnvironment,
+        "Messages without payloads are kept as is.",
+        false,
+    );
+
+    let output = trace.to_string();
+    assert!(output.contains("Reading bytes [1, [redacted]."));
+    assert!(output.contains("Messages without payloads are kept as is."));
+}
+
+#[test]
+fn filters_trace_points() {
+    let mut trace = StackTrace::default();
+    trace.enable();
+    trace.set_trace_points([TracePoint::DatabaseImpl]);
+
+    trace.maybe_add_trace(TracePoint::SorobanEnvironment, "Soroban trace.", false);
+    trace.maybe_add_trace(TracePoint::DatabaseImpl, "Database trace.", false);
+
+    let output = trace.to_string();
+    assert!(output.contains("Database trace."));
+    assert!(!output.contains("Soroban trace."));
+}
+
+#[test]
+fn host_filters_trace_points() {
+    use crate::{
+        host::Host,
+        testutils::{database::LedgerReader, RecordingDatabase},
+        ZephyrMock,
+    };
+
+    let mut host: Host<RecordingDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.as_stack_trace_mut().enable();
+    host.set_trace_points(&[TracePoint::LedgerImpl]);
+
+    let mut trace = host.as_stack_trace_mut();
+    trace.maybe_add_trace(TracePoint::ZephyrEnvironment, "Zephyr trace.", false);
+    trace.maybe_add_trace(TracePoint::LedgerImpl, "Ledger trace.", false);
+    drop(trace);
+
+    let output = host.read_stack_trace().to_string();
+    assert!(output.contains("Ledger trace."));
+    assert!(!output.contains("Zephyr trace."));
+}
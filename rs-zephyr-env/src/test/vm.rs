@@ -0,0 +1,445 @@
+use super::wasm;
+use crate::{
+    error::HostError,
+    host::Host,
+    testutils::database::{LedgerReader, MercuryDatabase},
+    vm::{Vm, VmConfig},
+    ZephyrMock,
+};
+use wasmi::{core::TrapCode, Engine};
+
+const BULK_MEMORY_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "fill") i32.const 0 i32.const 0 i32.const 0 memory.fill))
+"#;
+
+// Same as BULK_MEMORY_MODULE with the memory exported as "mem".
+const RENAMED_MEMORY_MODULE: &str = r#"
+(module
+  (memory (export "mem") 1)
+  (func (export "fill") i32.const 0 i32.const 0 i32.const 0 memory.fill))
+"#;
+
+const INFINITE_LOOP_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "spin") (loop (br 0))))
+"#;
+
+const UNREACHABLE_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "trap") unreachable))
+"#;
+
+const HOST_CALL_LOOP_MODULE: &str = r#"
+(module
+  (import "env" "read_affected_rows" (func (result i64)))
+  (memory (export "memory") 1)
+  (func (export "spin") (loop (call 0) (drop) (br 0))))
+"#;
+
+// Streams "ab" and traps before the last chunk, streams "cd" as a complete
+// result, or streams "abcd" in two chunks.
+const RESULT_CHUNKS_MODULE: &str = r#"
+(module
+  (import "env" "conclude_chunk" (func $chunk (param i64 i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "abcd")
+  (func (export "partial")
+    (drop (call $chunk (i64.const 0) (i64.const 2) (i64.const 0)))
+    unreachable)
+  (func (export "complete")
+    (drop (call $chunk (i64.const 2) (i64.const 2) (i64.const 1))))
+  (func (export "stream")
+    (drop (call $chunk (i64.const 0) (i64.const 2) (i64.const 0)))
+    (drop (call $chunk (i64.const 2) (i64.const 2) (i64.const 1)))))
+"#;
+
+// Concludes with the bytes "ab", or returns without a result.
+const BINARY_RESULT_MODULE: &str = r#"
+(module
+  (import "env" "conclude_binary" (func $conclude (param i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "ab")
+  (func (export "conclude")
+    (drop (call $conclude (i64.const 0) (i64.const 2))))
+  (func (export "empty")))
+"#;
+
+// Writes the checkpoint "ab" and traps, or concludes with the last checkpoint.
+const CHECKPOINT_MODULE: &str = r#"
+(module
+  (import "env" "write_checkpoint" (func $write (param i64 i64) (result i64)))
+  (import "env" "read_checkpoint" (func $read (result i64 i64 i64)))
+  (import "env" "conclude_binary" (func $conclude (param i64 i64) (result i64)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "ab")
+  (func (export "save")
+    (drop (call $write (i64.const 0) (i64.const 2)))
+    unreachable)
+  (func (export "load") (local $offset i64) (local $size i64)
+    (call $read)
+    (local.set $size)
+    (local.set $offset)
+    (drop)
+    (drop (call $conclude (local.get $offset) (local.get $size)))))
+"#;
+
+// read_raw as imported by a guest built without multi-value, with the
+// results lowered to a return pointer.
+const LOWERED_MULTI_VALUE_MODULE: &str = r#"
+(module
+  (import "env" "read_raw" (func (param i32))))
+"#;
+
+#[test]
+fn default_config_allows_bulk_memory() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+
+    assert!(Vm::new(&host, &wasm(BULK_MEMORY_MODULE)).is_ok());
+}
+
+#[test]
+fn rejects_disabled_features() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let config = VmConfig {
+        bulk_memory: false,
+        validate: true,
+        ..Default::default()
+    };
+
+    assert!(Vm::new_with_config(&host, &wasm(BULK_MEMORY_MODULE), &config).is_err());
+}
+
+#[test]
+fn disabled_features_force_validation() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let config = VmConfig {
+        bulk_memory: false,
+        ..Default::default()
+    };
+
+    assert!(!config.validate);
+    assert!(Vm::new_with_config(&host, &wasm(BULK_MEMORY_MODULE), &config).is_err());
+}
+
+#[test]
+fn rejects_missing_memory_export() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let error = Vm::new(&host, &wasm("(module)")).err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::NoMemoryExport)
+    ));
+}
+
+#[test]
+fn custom_memory_export_name() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let config = VmConfig {
+        memory_export_name: "mem".into(),
+        ..Default::default()
+    };
+
+    assert!(Vm::new_with_config(&host, &wasm(BULK_MEMORY_MODULE), &config).is_err());
+    assert!(Vm::new_with_config(&host, &wasm(RENAMED_MEMORY_MODULE), &config).is_ok());
+}
+
+#[test]
+fn lists_exported_functions() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &wasm(BULK_MEMORY_MODULE)).unwrap();
+
+    let exported = vm.exported_functions();
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0].0, "fill");
+    assert!(exported[0].1.params().is_empty());
+    assert!(exported[0].1.results().is_empty());
+}
+
+#[test]
+fn shares_host_functions_between_instantiations() {
+    use std::rc::Rc;
+
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let other: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+
+    assert!(Rc::ptr_eq(
+        &Vm::host_functions(&host),
+        &Vm::host_functions(&other)
+    ));
+}
+
+#[test]
+fn dropping_the_vm_releases_its_engine() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+
+    let first = Vm::new(&host, &wasm(HOST_CALL_LOOP_MODULE)).unwrap();
+    let second = Vm::new(&host, &wasm(HOST_CALL_LOOP_MODULE)).unwrap();
+    let engine = first.store.borrow().engine().weak();
+    assert!(!Engine::same(
+        first.store.borrow().engine(),
+        second.store.borrow().engine()
+    ));
+
+    // The compiled code is owned by the engine, which is only referenced by
+    // the VM's store and module.
+    drop(first);
+    assert!(engine.upgrade().is_none());
+}
+
+#[test]
+fn reports_out_of_fuel() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &wasm(INFINITE_LOOP_MODULE)).unwrap();
+    vm.store.borrow_mut().set_fuel(10_000).unwrap();
+
+    let error = vm.metered_function_call(&host, "spin").err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::OutOfFuel)
+    ));
+}
+
+#[test]
+fn reports_trap_code() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.as_stack_trace_mut().enable();
+    let vm = Vm::new(&host, &wasm(UNREACHABLE_MODULE)).unwrap();
+
+    let error = vm.metered_function_call(&host, "trap").err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::Trap(TrapCode::UnreachableCodeReached))
+    ));
+    assert!(host
+        .read_stack_trace()
+        .to_string()
+        .contains("Program execution failed"));
+}
+
+#[test]
+fn caps_host_calls() {
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_max_host_calls(10);
+    let vm = Vm::new(&host, &wasm(HOST_CALL_LOOP_MODULE)).unwrap();
+
+    let error = vm.metered_function_call(&host, "spin").err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::HostCallQuotaExceeded)
+    ));
+    assert_eq!(host.host_calls(), 11);
+
+    // The quota applies to each invocation rather than to the instance.
+    assert!(vm.metered_function_call(&host, "spin").is_err());
+    assert_eq!(host.host_calls(), 11);
+}
+
+#[test]
+fn uncapped_host_calls_are_not_counted() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &wasm(HOST_CALL_LOOP_MODULE)).unwrap();
+    vm.store.borrow_mut().set_fuel(10_000).unwrap();
+
+    let error = vm.metered_function_call(&host, "spin").err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::OutOfFuel)
+    ));
+    assert_eq!(host.host_calls(), 0);
+}
+
+#[test]
+fn rejects_lowered_multi_value_imports() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+
+    let error = Vm::new(&host, &wasm(LOWERED_MULTI_VALUE_MODULE)).err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<HostError>(),
+        Some(HostError::MultiValueRequired(name)) if name == "read_raw"
+    ));
+}
+
+#[test]
+fn trapped_result_stream_is_discarded() {
+    use std::rc::Rc;
+
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &wasm(RESULT_CHUNKS_MODULE)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    assert!(vm.metered_function_call(&host, "partial").is_err());
+
+    // The chunk streamed before the trap isn't prepended to the next result.
+    vm.metered_function_call(&host, "complete").unwrap();
+    assert_eq!(host.read_result_bytes(), Some(b"cd".to_vec()));
+}
+
+#[test]
+fn caps_streamed_result_size() {
+    use std::rc::Rc;
+
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_max_result_bytes(3);
+    host.as_stack_trace_mut().enable();
+    let vm = Vm::new(&host, &wasm(RESULT_CHUNKS_MODULE)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    vm.metered_function_call(&host, "stream").unwrap();
+    assert_eq!(host.read_result_bytes(), None);
+    assert!(host
+        .read_stack_trace()
+        .to_string()
+        .contains("Streamed result exceeds the maximum of 3 bytes"));
+
+    host.set_max_result_bytes(4);
+    vm.metered_function_call(&host, "stream").unwrap();
+    assert_eq!(host.read_result_bytes(), Some(b"abcd".to_vec()));
+}
+
+#[test]
+fn binary_result_is_reset_per_invocation() {
+    use std::rc::Rc;
+
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &wasm(BINARY_RESULT_MODULE)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    vm.metered_function_call(&host, "conclude").unwrap();
+    assert_eq!(host.read_result_bytes(), Some(b"ab".to_vec()));
+
+    vm.metered_function_call(&host, "empty").unwrap();
+    assert_eq!(host.read_result_bytes(), None);
+}
+
+#[test]
+fn checkpoint_survives_traps() {
+    use std::rc::Rc;
+
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &wasm(CHECKPOINT_MODULE)).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    assert!(vm.metered_function_call(&host, "save").is_err());
+    assert_eq!(host.read_checkpoint(), Some(b"ab".to_vec()));
+
+    vm.metered_function_call(&host, "load").unwrap();
+    assert_eq!(host.read_result_bytes(), Some(b"ab".to_vec()));
+}
+
+#[test]
+fn reads_config_values() {
+    use std::rc::Rc;
+
+    let module = super::segment_call_module("read_config_value", b"threshold");
+
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_config("threshold", "10");
+    let vm = Vm::new(&host, &module).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    vm.metered_function_call(&host, "call").unwrap();
+    assert_eq!(host.read_result_bytes(), Some(b"10".to_vec()));
+
+    // Reading a value that wasn't set fails, so the module concludes with an
+    // empty segment.
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    let vm = Vm::new(&host, &module).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+
+    vm.metered_function_call(&host, "call").unwrap();
+    assert_eq!(host.read_result_bytes(), Some(Vec::new()));
+}
+
+/// Compiles a module with two pages of memory whose `call` export concludes
+/// with the little-endian value returned by the imported host function
+/// `name`, after the status code when `with_status` is set.
+fn value_call_module(name: &str, with_status: bool) -> Vec<u8> {
+    let (results, drop_status) = if with_status {
+        ("i64 i64", "(drop)")
+    } else {
+        ("i64", "")
+    };
+
+    wasm(&format!(
+        r#"
+(module
+  (import "env" "{}" (func $call (result {})))
+  (import "env" "conclude_binary" (func $conclude (param i64 i64) (result i64)))
+  (memory (export "memory") 2)
+  (func (export "call") (local $value i64)
+    (call $call)
+    (local.set $value)
+    {}
+    (i64.store (i32.const 0) (local.get $value))
+    (drop (call $conclude (i64.const 0) (i64.const 8)))))
+"#,
+        name, results, drop_status
+    ))
+}
+
+/// Invokes the `call` export of a [`value_call_module`] module and returns
+/// the value it concluded with.
+fn call_value(host: &Host<MercuryDatabase, LedgerReader>, module: &[u8]) -> i64 {
+    use std::rc::Rc;
+
+    let vm = Vm::new(host, module).unwrap();
+    host.load_context(Rc::downgrade(&vm)).unwrap();
+    vm.metered_function_call(host, "call").unwrap();
+
+    i64::from_le_bytes(host.read_result_bytes().unwrap().try_into().unwrap())
+}
+
+#[test]
+fn reads_memory_pages() {
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+
+    assert_eq!(
+        call_value(&host, &value_call_module("memory_pages", true)),
+        2
+    );
+}
+
+#[test]
+fn reads_input_kind() {
+    use crate::host::InputKind;
+
+    let module = value_call_module("read_input_kind", true);
+
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.add_ledger_close_meta(vec![0]).unwrap();
+    assert_eq!(
+        call_value(&host, &module),
+        InputKind::LedgerCloseMeta as i64
+    );
+
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.add_function_input(vec![0]).unwrap();
+    assert_eq!(call_value(&host, &module), InputKind::FunctionArgs as i64);
+}
+
+#[test]
+fn reads_catchup_mode() {
+    let module = value_call_module("is_catchup", false);
+
+    let host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    assert_eq!(call_value(&host, &module), 0);
+
+    let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
+    host.set_catchup_mode(true);
+    assert_eq!(call_value(&host, &module), 1);
+}
+
+#[test]
+fn reads_host_id() {
+    let host: Host<MercuryDatabase, LedgerReader> =
+        Host::from_id_with_ledger(42, [0; 32], 1, 0).unwrap();
+
+    assert_eq!(
+        call_value(&host, &value_call_module("get_host_id", false)),
+        42
+    );
+}
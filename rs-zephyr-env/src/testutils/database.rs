@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use super::symbol;
 use crate::{
@@ -6,7 +6,7 @@ use crate::{
         database::{WhereCond, ZephyrDatabase},
         ledger::LedgerStateRead,
     },
-    ZephyrMock,
+    ZephyrMock, ZephyrStandard,
 };
 use anyhow::Result;
 use postgres::{
@@ -14,7 +14,7 @@ use postgres::{
     types::{ToSql, Type},
     Client, NoTls,
 };
-use rs_zephyr_common::{ContractDataEntry, DatabaseError, ZephyrVal};
+use rs_zephyr_common::{Account, ContractDataEntry, DatabaseError, ZephyrVal};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
@@ -50,6 +50,65 @@ impl ZephyrMock for LedgerReader {
     }
 }
 
+impl ZephyrStandard for LedgerReader {
+    fn zephyr_standard() -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {})
+    }
+}
+
+/// Ledger test double returning the contract data entries set with
+/// [`SeededLedger::set_entries`]. It holds no accounts.
+#[derive(Clone, Default)]
+pub struct SeededLedger {
+    entries: Rc<RefCell<Vec<ContractDataEntry>>>,
+}
+
+impl SeededLedger {
+    /// Sets the contract data entries of the ledger.
+    pub fn set_entries(&self, entries: Vec<ContractDataEntry>) {
+        *self.entries.borrow_mut() = entries;
+    }
+}
+
+impl LedgerStateRead for SeededLedger {
+    fn read_contract_data_entry_by_contract_id_and_key(
+        &self,
+        contract: soroban_env_host::xdr::ScAddress,
+        key: soroban_env_host::xdr::ScVal,
+    ) -> Option<ContractDataEntry> {
+        self.entries
+            .borrow()
+            .iter()
+            .find(|entry| entry.contract_id == contract && entry.key == key)
+            .cloned()
+    }
+
+    fn read_contract_data_entries_by_contract_id(
+        &self,
+        contract: soroban_env_host::xdr::ScAddress,
+    ) -> Vec<ContractDataEntry> {
+        self.entries
+            .borrow()
+            .iter()
+            .filter(|entry| entry.contract_id == contract)
+            .cloned()
+            .collect()
+    }
+
+    fn read_account(&self, _account: String) -> Option<Account> {
+        None
+    }
+}
+
+impl ZephyrMock for SeededLedger {
+    fn mocked() -> Result<Self> {
+        Ok(Self::default())
+    }
+}
+
 #[derive(Clone)]
 pub struct MercuryDatabase {
     pub postgres_arg: String,
@@ -63,6 +122,218 @@ impl ZephyrMock for MercuryDatabase {
     }
 }
 
+impl ZephyrStandard for MercuryDatabase {
+    fn zephyr_standard() -> Result<Self> {
+        Self::mocked()
+    }
+}
+
+/// Database operation requested by a program, as recorded by [`RecordingDatabase`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum DatabaseOperation {
+    /// Rows read from a table.
+    Read {
+        /// Name of the table.
+        table: String,
+        /// Columns read.
+        columns: Vec<String>,
+        /// Conditions the rows must satisfy.
+        conditions: Option<Vec<WhereCond>>,
+        /// Arguments of the conditions.
+        condition_args: Option<Vec<Vec<u8>>>,
+    },
+
+    /// Row inserted in a table.
+    Write {
+        /// Name of the table.
+        table: String,
+        /// Columns written.
+        columns: Vec<String>,
+        /// Serialized values, one for each column.
+        values: Vec<Vec<u8>>,
+        /// Number of ledgers after which the row expires, if any.
+        ttl_ledgers: Option<u32>,
+    },
+
+    /// Rows updated in a table.
+    Update {
+        /// Name of the table.
+        table: String,
+        /// Columns updated.
+        columns: Vec<String>,
+        /// Serialized values, one for each column.
+        values: Vec<Vec<u8>>,
+        /// Conditions the updated rows must satisfy.
+        conditions: Vec<WhereCond>,
+        /// Arguments of the conditions.
+        condition_args: Vec<Vec<u8>>,
+    },
+}
+
+/// Database test double that doesn't require postgres. It records every
+/// operation the program performs so that tests can assert on them.
+///
+/// Reads return the rows set with [`RecordingDatabase::set_read_rows`], no
+/// rows by default, and updates report no affected rows.
+#[derive(Clone, Default)]
+pub struct RecordingDatabase {
+    operations: Rc<RefCell<Vec<DatabaseOperation>>>,
+    read_rows: Rc<RefCell<Vec<TableRow>>>,
+}
+
+impl RecordingDatabase {
+    /// Returns all the recorded operations in the order they were performed.
+    pub fn operations(&self) -> Vec<DatabaseOperation> {
+        self.operations.borrow().clone()
+    }
+
+    /// Returns the recorded write operations.
+    pub fn writes(&self) -> Vec<DatabaseOperation> {
+        self.operations
+            .borrow()
+            .iter()
+            .filter(|op| matches!(op, DatabaseOperation::Write { .. }))
+            .cloned()
+            .collect()
+    }
+
+    /// Sets the rows returned by every read, each row being the serialized
+    /// value of its columns.
+    pub fn set_read_rows(&self, rows: Vec<Vec<Vec<u8>>>) {
+        *self.read_rows.borrow_mut() = rows
+            .into_iter()
+            .map(|row| TableRow {
+                row: row.into_iter().map(TypeWrap).collect(),
+            })
+            .collect();
+    }
+
+    fn record(&self, operation: DatabaseOperation) {
+        self.operations.borrow_mut().push(operation)
+    }
+}
+
+fn recorded_table_and_columns(
+    point_hash: [u8; 16],
+    columns: &[i64],
+) -> Result<(String, Vec<String>), DatabaseError> {
+    let table = format!("zephyr_{}", hex::encode(point_hash).as_str());
+    let columns = columns
+        .iter()
+        .map(|column| symbol::Symbol(*column as u64).to_string())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| DatabaseError::ZephyrQueryMalformed)?;
+
+    Ok((table, columns))
+}
+
+impl ZephyrMock for RecordingDatabase {
+    fn mocked() -> Result<Self> {
+        Ok(Self::default())
+    }
+}
+
+impl ZephyrDatabase for RecordingDatabase {
+    fn read_raw(
+        &self,
+        _: i64,
+        read_point_hash: [u8; 16],
+        read_data: &[i64],
+        condition: Option<&[WhereCond]>,
+        condition_args: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, DatabaseError> {
+        let (table, columns) = recorded_table_and_columns(read_point_hash, read_data)?;
+        self.record(DatabaseOperation::Read {
+            table,
+            columns,
+            conditions: condition.map(|condition| condition.to_vec()),
+            condition_args,
+        });
+
+        let rows = self.read_rows.borrow().clone();
+        Ok(bincode::serialize(&TableRows { rows }).unwrap())
+    }
+
+    fn read_latest_raw(
+        &self,
+        user_id: i64,
+        read_point_hash: [u8; 16],
+        read_data: &[i64],
+        condition: Option<&[WhereCond]>,
+        condition_args: Option<Vec<Vec<u8>>>,
+        _: i64,
+    ) -> Result<Vec<u8>, DatabaseError> {
+        self.read_raw(
+            user_id,
+            read_point_hash,
+            read_data,
+            condition,
+            condition_args,
+        )
+    }
+
+    fn write_raw(
+        &self,
+        _: i64,
+        written_point_hash: [u8; 16],
+        write_data: &[i64],
+        written: Vec<Vec<u8>>,
+    ) -> Result<(), DatabaseError> {
+        let (table, columns) = recorded_table_and_columns(written_point_hash, write_data)?;
+        self.record(DatabaseOperation::Write {
+            table,
+            columns,
+            values: written,
+            ttl_ledgers: None,
+        });
+
+        Ok(())
+    }
+
+    fn write_raw_with_ttl(
+        &self,
+        _: i64,
+        written_point_hash: [u8; 16],
+        write_data: &[i64],
+        written: Vec<Vec<u8>>,
+        ttl_ledgers: u32,
+    ) -> Result<(), DatabaseError> {
+        let (table, columns) = recorded_table_and_columns(written_point_hash, write_data)?;
+        self.record(DatabaseOperation::Write {
+            table,
+            columns,
+            values: written,
+            ttl_ledgers: Some(ttl_ledgers),
+        });
+
+        Ok(())
+    }
+
+    fn update_raw(
+        &self,
+        _: i64,
+        written_point_hash: [u8; 16],
+        write_data: &[i64],
+        written: Vec<Vec<u8>>,
+        condition: &[WhereCond],
+        condition_args: Vec<Vec<u8>>,
+    ) -> Result<u64, DatabaseError> {
+        let (table, columns) = recorded_table_and_columns(written_point_hash, write_data)?;
+        self.record(DatabaseOperation::Update {
+            table,
+            columns,
+            values: written,
+            conditions: condition.to_vec(),
+            condition_args,
+        });
+
+        Ok(0)
+    }
+}
+
+/// Column of the local tables in which the ttl of expiring rows is stored.
+pub const TTL_COLUMN: &str = "ttl_ledgers";
+
 pub enum WriteParam {
     Bytes(Vec<u8>),
     Integer(i64),
@@ -77,25 +348,18 @@ impl WriteParam {
     }
 }
 
-impl ZephyrDatabase for MercuryDatabase {
-    fn read_raw(
+impl MercuryDatabase {
+    /// Reads rows of a table, only keeping the row with the greatest
+    /// `latest_by` column when provided.
+    fn read_rows(
         &self,
-        _: i64,
         read_point_hash: [u8; 16],
         read_data: &[i64],
         condition: Option<&[WhereCond]>,
         condition_args: Option<Vec<Vec<u8>>>,
+        latest_by: Option<i64>,
     ) -> Result<Vec<u8>, DatabaseError> {
         let table_name = format!("zephyr_{}", hex::encode(read_point_hash).as_str());
-        let mut columns: Vec<String> = Vec::new();
-
-        for val in read_data {
-            if let Ok(res) = symbol::Symbol(*val as u64).to_string() {
-                columns.push(res);
-            } else {
-                return Err(DatabaseError::ZephyrQueryError);
-            }
-        }
 
         let mut client = if let Ok(client) = Client::connect(&self.postgres_arg, NoTls) {
             client
@@ -105,12 +369,18 @@ impl ZephyrDatabase for MercuryDatabase {
 
         let types_map = get_table_types(&mut client, &table_name);
 
+        let mut columns: Vec<String> = Vec::new();
+        for val in read_data {
+            let column = checked_column(*val, &types_map).ok_or(DatabaseError::ZephyrQueryError)?;
+            columns.push(column);
+        }
+
         let mut columns_string = String::new();
         for (idx, column) in columns.iter().enumerate() {
             if idx == columns.len() - 1 {
-                columns_string.push_str(&format!("{}", column))
+                columns_string.push_str(&quote_identifier(column))
             } else {
-                columns_string.push_str(&format!("{}, ", column))
+                columns_string.push_str(&format!("{}, ", quote_identifier(column)))
             }
         }
 
@@ -131,11 +401,11 @@ impl ZephyrDatabase for MercuryDatabase {
                         WhereCond::ColLt(column) => ("<", column),
                     };
 
-                    let colname = symbol::Symbol(column as u64)
-                        .to_string()
-                        .map_err(|_| DatabaseError::WriteError)?;
+                    let colname =
+                        checked_column(column, &types_map).ok_or(DatabaseError::WriteError)?;
 
-                    let condition_str = format!("{} {} ${}", colname, operator, idx + 1);
+                    let condition_str =
+                        format!("{} {} ${}", quote_identifier(&colname), operator, idx + 1);
                     if idx != condition.len() - 1 {
                         query.push_str(&format!("{} AND ", condition_str));
                     } else {
@@ -174,6 +444,15 @@ impl ZephyrDatabase for MercuryDatabase {
             //            }
         }
 
+        if let Some(order_column) = latest_by {
+            let order_column =
+                checked_column(order_column, &types_map).ok_or(DatabaseError::ZephyrQueryError)?;
+            query.push_str(&format!(
+                " ORDER BY {} DESC LIMIT 1",
+                quote_identifier(&order_column)
+            ));
+        }
+
         let stmt = if let Ok(stmt) = client.prepare_typed(&query, &types) {
             stmt
         } else {
@@ -214,12 +493,15 @@ impl ZephyrDatabase for MercuryDatabase {
         Ok(bincode::serialize(&result).unwrap())
     }
 
-    fn write_raw(
+    /// Inserts a row in a table. The local database doesn't track ledgers, so
+    /// rows with a ttl don't expire: the ttl is stored in the table's
+    /// [`TTL_COLUMN`] for tests to check it.
+    fn insert_row(
         &self,
-        _: i64,
         written_point_hash: [u8; 16],
         write_data: &[i64],
         written: Vec<Vec<u8>>,
+        ttl_ledgers: Option<u32>,
     ) -> Result<(), DatabaseError> {
         let connection = Client::connect(&self.postgres_arg, NoTls);
         let mut client = if let Ok(client) = connection {
@@ -244,13 +526,10 @@ impl ZephyrDatabase for MercuryDatabase {
         query.push_str(" (");
 
         for idx in 0..write_data.len() {
-            let col = if let Ok(string) = symbol::Symbol(write_data[idx] as u64).to_string() {
-                string
-            } else {
-                return Err(DatabaseError::WriteError);
-            };
+            let col =
+                checked_column(write_data[idx], &types_map).ok_or(DatabaseError::WriteError)?;
             let bytes = &written[idx];
-            query.push_str(&col);
+            query.push_str(&quote_identifier(&col));
 
             if types_map.get(&col).unwrap() == "bigint" {
                 let param_deser: ZephyrVal =
@@ -274,6 +553,16 @@ impl ZephyrDatabase for MercuryDatabase {
                 query.push_str(", ");
             }
         }
+
+        if let Some(ttl_ledgers) = ttl_ledgers {
+            if types_map.get(TTL_COLUMN).map(String::as_str) != Some("bigint") {
+                return Err(DatabaseError::WriteError);
+            }
+
+            query.push_str(&format!(", {}", quote_identifier(TTL_COLUMN)));
+            owned_params.push(WriteParam::Integer(ttl_ledgers as i64));
+            types.push(Type::INT8)
+        }
         query.push(')');
 
         /*let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
@@ -314,6 +603,58 @@ impl ZephyrDatabase for MercuryDatabase {
             Err(DatabaseError::WriteError)
         }
     }
+}
+
+impl ZephyrDatabase for MercuryDatabase {
+    fn read_raw(
+        &self,
+        _: i64,
+        read_point_hash: [u8; 16],
+        read_data: &[i64],
+        condition: Option<&[WhereCond]>,
+        condition_args: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, DatabaseError> {
+        self.read_rows(read_point_hash, read_data, condition, condition_args, None)
+    }
+
+    fn read_latest_raw(
+        &self,
+        _: i64,
+        read_point_hash: [u8; 16],
+        read_data: &[i64],
+        condition: Option<&[WhereCond]>,
+        condition_args: Option<Vec<Vec<u8>>>,
+        order_column: i64,
+    ) -> Result<Vec<u8>, DatabaseError> {
+        self.read_rows(
+            read_point_hash,
+            read_data,
+            condition,
+            condition_args,
+            Some(order_column),
+        )
+    }
+
+    fn write_raw(
+        &self,
+        _: i64,
+        written_point_hash: [u8; 16],
+        write_data: &[i64],
+        written: Vec<Vec<u8>>,
+    ) -> Result<(), DatabaseError> {
+        self.insert_row(written_point_hash, write_data, written, None)
+    }
+
+    fn write_raw_with_ttl(
+        &self,
+        _: i64,
+        written_point_hash: [u8; 16],
+        write_data: &[i64],
+        written: Vec<Vec<u8>>,
+        ttl_ledgers: u32,
+    ) -> Result<(), DatabaseError> {
+        self.insert_row(written_point_hash, write_data, written, Some(ttl_ledgers))
+    }
 
     fn update_raw(
         &self,
@@ -323,7 +664,7 @@ impl ZephyrDatabase for MercuryDatabase {
         written: Vec<Vec<u8>>,
         condition: &[WhereCond],
         condition_args: Vec<Vec<u8>>,
-    ) -> Result<(), DatabaseError> {
+    ) -> Result<u64, DatabaseError> {
         let connection = Client::connect(&self.postgres_arg, NoTls);
         let table_name = format!("zephyr_{}", hex::encode(written_point_hash).as_str());
 
@@ -345,14 +686,11 @@ impl ZephyrDatabase for MercuryDatabase {
         query.push_str(" SET ");
 
         for idx in 0..write_data.len() {
-            let col = if let Ok(string) = symbol::Symbol(write_data[idx] as u64).to_string() {
-                string
-            } else {
-                return Err(DatabaseError::WriteError);
-            };
+            let col =
+                checked_column(write_data[idx], &types_map).ok_or(DatabaseError::WriteError)?;
             let bytes = &written[idx];
 
-            query.push_str(&col);
+            query.push_str(&quote_identifier(&col));
 
             if idx != write_data.len() - 1 {
                 query.push_str(&format!(" = ${}, ", idx + 1));
@@ -393,12 +731,15 @@ impl ZephyrDatabase for MercuryDatabase {
                     WhereCond::ColLt(column) => ("<", column),
                 };
 
-                let colname = symbol::Symbol(column as u64)
-                    .to_string()
-                    .map_err(|_| DatabaseError::WriteError)?;
+                let colname =
+                    checked_column(column, &types_map).ok_or(DatabaseError::WriteError)?;
 
-                let condition_str =
-                    format!("{} {} ${}", colname, operator, write_data.len() + idx + 1);
+                let condition_str = format!(
+                    "{} {} ${}",
+                    quote_identifier(&colname),
+                    operator,
+                    write_data.len() + idx + 1
+                );
                 if idx != condition.len() - 1 {
                     query.push_str(&format!("{} AND ", condition_str));
                 } else {
@@ -444,14 +785,37 @@ impl ZephyrDatabase for MercuryDatabase {
 
         let params: Vec<&(dyn ToSql + Sync)> =
             owned_params.iter().map(|param| param.as_tosql()).collect();
-        if let Ok(_) = client.execute(&statement, &params) {
-            Ok(())
+        if let Ok(affected) = client.execute(&statement, &params) {
+            Ok(affected)
         } else {
             Err(DatabaseError::WriteError)
         }
     }
 }
 
+/// Decodes a guest-provided column name, only accepting symbol characters and
+/// columns that exist in the table schema, so that column names can't be used
+/// to inject SQL.
+pub(crate) fn checked_column(column: i64, types_map: &HashMap<String, String>) -> Option<String> {
+    let name = symbol::Symbol(column as u64).to_string().ok()?;
+
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        || !types_map.contains_key(&name)
+    {
+        return None;
+    }
+
+    Some(name)
+}
+
+/// Quotes a column name checked with [`checked_column`] as an SQL identifier.
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name)
+}
+
 fn get_table_types(client: &mut Client, table_name: &str) -> HashMap<String, String> {
     let mut types_map = HashMap::new();
     let query = format!(
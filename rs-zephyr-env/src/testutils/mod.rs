@@ -10,10 +10,14 @@
 //!
 pub(crate) mod database;
 pub(crate) mod symbol;
+pub use database::{DatabaseOperation, RecordingDatabase, SeededLedger, TTL_COLUMN};
 pub use ledger_meta_factory::{Transition, TransitionPretty};
 
 use crate::{
-    host::{utils, Host},
+    db::database::ZephyrDatabase,
+    error::{HttpRelayError, RelayError},
+    host::{utils, Host, InputKind},
+    relay,
     trace::StackTrace,
     vm::Vm,
     ZephyrMock,
@@ -22,13 +26,14 @@ use anyhow::Result as AnyResult;
 use database::{LedgerReader, MercuryDatabase};
 use postgres::NoTls;
 use reqwest::{
-    header::{HeaderMap, HeaderName},
-    Client,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, StatusCode,
 };
-use rs_zephyr_common::{http::Method, RelayedMessageRequest};
+use rs_zephyr_common::{http::Method, log::ZephyrLog, RelayedMessageRequest};
+use serde::Serialize;
 use std::{collections::HashMap, fs::File, io::Read, rc::Rc, str::FromStr};
 use symbol::Symbol;
-use tokio::task::JoinError;
+use tokio::{sync::mpsc::UnboundedReceiver, task::JoinError};
 
 /// Zephyr testing utility object.
 #[derive(Default)]
@@ -55,10 +60,44 @@ pub(crate) fn read_wasm(path: &str) -> Vec<u8> {
     binary.to_vec()
 }
 
+/// Outcome of a [`TestVM::invoke_vm_recorded`] invocation.
+pub struct RecordedInvocation {
+    /// Result of the invoked function.
+    pub result: AnyResult<String>,
+
+    /// Stack trace of the invocation.
+    pub stack_trace: StackTrace,
+
+    /// Database operations performed by the program, in order.
+    pub operations: Vec<DatabaseOperation>,
+
+    /// Logs emitted by the program, in order.
+    pub logs: Vec<ZephyrLog>,
+
+    /// Status codes of the HTTP requests relayed by the program, in the order
+    /// the requests were sent, or the error of the requests that failed.
+    pub http_responses: Vec<Result<StatusCode, HttpRelayError>>,
+
+    /// Errors of the relayed messages that couldn't be decoded.
+    pub skipped_messages: Vec<RelayError>,
+}
+
+impl RecordedInvocation {
+    /// Returns the database writes performed by the program.
+    pub fn db_writes(&self) -> Vec<&DatabaseOperation> {
+        self.operations
+            .iter()
+            .filter(|op| matches!(op, DatabaseOperation::Write { .. }))
+            .collect()
+    }
+}
+
 /// Testing utility object representing the Zephyr Virtual Machine.
 pub struct TestVM {
     wasm_path: String,
     ledger_close_meta: Option<Vec<u8>>,
+    input_kind: InputKind,
+    deterministic_relay: bool,
 }
 
 impl TestVM {
@@ -67,19 +106,60 @@ impl TestVM {
         Self {
             wasm_path: path.to_string(),
             ledger_close_meta: None,
+            input_kind: InputKind::LedgerCloseMeta,
+            deterministic_relay: false,
         }
     }
 
     /// Sets a new ledger transition XDR or replaces the existing one.
     pub fn set_transition(&mut self, transition: Transition) {
         let meta = transition.to_bytes();
-        self.ledger_close_meta = Some(meta)
+        self.ledger_close_meta = Some(meta);
+        self.input_kind = InputKind::LedgerCloseMeta;
+    }
+
+    /// Processes the messages relayed by the program strictly in order,
+    /// waiting for each HTTP request to complete before handling the next
+    /// message. Useful for reproducible tests.
+    pub fn set_deterministic_relay(&mut self, active: bool) {
+        self.deterministic_relay = active
     }
 
     /// Sets a new funciton body or replaces the existing one.
     pub fn set_body(&mut self, body: String) {
-        let meta = bincode::serialize(&body).unwrap();
-        self.ledger_close_meta = Some(meta)
+        self.set_function_input(&body)
+    }
+
+    /// Sets the typed arguments of a function invocation or replaces the
+    /// existing ones. The arguments are bincode-serialized, which is what
+    /// function programs decode their input from.
+    pub fn set_function_input<T: Serialize>(&mut self, args: &T) {
+        let input = bincode::serialize(args).unwrap();
+        self.ledger_close_meta = Some(input);
+        self.input_kind = InputKind::FunctionArgs;
+    }
+
+    /// Invokes the selected function exported by the current ZephyrVM against a
+    /// [`RecordingDatabase`] rather than postgres, returning the database
+    /// operations and logs of the program alongside its result.
+    pub async fn invoke_vm_recorded(
+        &self,
+        fname: impl ToString,
+    ) -> Result<AnyResult<RecordedInvocation>, JoinError> {
+        let invocation = self
+            .invoke_with(fname, |host: &Host<RecordingDatabase, LedgerReader>| {
+                host.as_database().0.db.operations()
+            })
+            .await?;
+
+        Ok(invocation.map(|invocation| RecordedInvocation {
+            result: invocation.result,
+            stack_trace: invocation.stack_trace,
+            operations: invocation.inspected,
+            logs: invocation.relayed.logs,
+            http_responses: invocation.relayed.http_responses,
+            skipped_messages: invocation.relayed.skipped,
+        }))
     }
 
     /// Invokes the selected function exported by the current ZephyrVM.
@@ -88,84 +168,169 @@ impl TestVM {
         &self,
         fname: impl ToString,
     ) -> Result<AnyResult<(AnyResult<String>, StackTrace)>, JoinError> {
-        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+        let invocation = self
+            .invoke_with(fname, |_: &Host<MercuryDatabase, LedgerReader>| ())
+            .await?;
+
+        Ok(invocation.map(|invocation| (invocation.result, invocation.stack_trace)))
+    }
+
+    /// Invokes the selected function on a mocked host backed by `DB`, reading
+    /// what the caller needs from the host with `inspect` once the function
+    /// returns.
+    async fn invoke_with<DB, T>(
+        &self,
+        fname: impl ToString,
+        inspect: fn(&Host<DB, LedgerReader>) -> T,
+    ) -> Result<AnyResult<Invocation<T>>, JoinError>
+    where
+        DB: ZephyrDatabase + ZephyrMock + Clone + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
         let fname = fname.to_string();
         let wasm_path = self.wasm_path.clone();
         let meta = self.ledger_close_meta.clone();
+        let input_kind = self.input_kind;
+        let deterministic = self.deterministic_relay;
 
         let invocation = tokio::runtime::Handle::current()
-            .spawn_blocking(move || {
-                let mut host: Host<MercuryDatabase, LedgerReader> = Host::mocked().unwrap();
-                
+            .spawn_blocking(move || -> AnyResult<_> {
+                let mut host: Host<DB, LedgerReader> = Host::mocked()?;
+
                 host.set_stack_trace(true);
-                let vm = Vm::new(&host, &read_wasm(&wasm_path)).unwrap();
-                host.load_context(Rc::downgrade(&vm)).unwrap();
+                let vm = Vm::new(&host, &read_wasm(&wasm_path))?;
+                host.load_context(Rc::downgrade(&vm))?;
                 host.add_transmitter(tx);
 
                 if let Some(meta) = meta {
-                    host.add_ledger_close_meta(meta).unwrap();
+                    match input_kind {
+                        InputKind::LedgerCloseMeta => host.add_ledger_close_meta(meta)?,
+                        InputKind::FunctionArgs => host.add_function_input(meta)?,
+                    }
                 };
 
                 let result = vm.metered_function_call(&host, &fname);
                 let stack_trace = host.read_stack_trace();
 
-                Ok((result, stack_trace))
+                Ok((result, stack_trace, inspect(&host)))
             })
             .await;
 
-        let _ = tokio::spawn(async move {
-            let mut handles = Vec::new();
-            while let Some(message) = rx.recv().await {
-                let request: RelayedMessageRequest = bincode::deserialize(&message).unwrap();
-                match request {
-                    RelayedMessageRequest::Http(request) => {
-                        let handle = tokio::spawn(async move {
-                            let client = Client::new();
-                            let mut headers = HeaderMap::new();
-                            for (k, v) in &request.headers {
-                                headers
-                                    .insert(HeaderName::from_str(&k).unwrap(), v.parse().unwrap());
-                            }
-                            let builder = match request.method {
-                                Method::Get => {
-                                    let builder = client.get(&request.url).headers(headers);
-
-                                    if let Some(body) = &request.body {
-                                        builder.body(body.clone())
-                                    } else {
-                                        builder
-                                    }
-                                }
-                                Method::Post => {
-                                    let builder = client.post(&request.url).headers(headers);
-
-                                    if let Some(body) = &request.body {
-                                        builder.body(body.clone())
-                                    } else {
-                                        builder
-                                    }
-                                }
-                            };
-                            let resp = builder.send().await;
-                            println!("response: {:?}", resp);
-                        });
-
-                        handles.push(handle)
-                    }
-                    RelayedMessageRequest::Log(log) => {
-                        println!("{:?}", log);
-                    }
-                }
+        let relayed = tokio::spawn(relay_messages(rx, deterministic)).await?;
+
+        invocation.map(|invocation| {
+            invocation.map(|(result, stack_trace, inspected)| Invocation {
+                result,
+                stack_trace,
+                inspected,
+                relayed,
+            })
+        })
+    }
+}
+
+/// Outcome of a [`TestVM::invoke_with`] invocation.
+struct Invocation<T> {
+    result: AnyResult<String>,
+    stack_trace: StackTrace,
+    inspected: T,
+    relayed: RelayedMessages,
+}
+
+/// Messages relayed by a program, see [`relay_messages`].
+#[derive(Default)]
+pub(crate) struct RelayedMessages {
+    pub(crate) logs: Vec<ZephyrLog>,
+    pub(crate) http_responses: Vec<Result<StatusCode, HttpRelayError>>,
+    pub(crate) skipped: Vec<RelayError>,
+}
+
+/// Relays the messages sent by a program until the host drops the transmitter,
+/// returning the logs it emitted, the outcome of its HTTP requests and the
+/// messages that couldn't be decoded.
+///
+/// HTTP requests are sent concurrently unless `deterministic` is set, in which
+/// case every message is fully processed in order before the next one.
+pub(crate) async fn relay_messages(
+    mut rx: UnboundedReceiver<Vec<u8>>,
+    deterministic: bool,
+) -> RelayedMessages {
+    let mut handles = Vec::new();
+    let mut relayed = RelayedMessages::default();
+    while let Some(message) = rx.recv().await {
+        let request = match relay::decode_message(&message) {
+            Ok(request) => request,
+            Err(error) => {
+                relayed.skipped.push(error);
+                continue;
             }
+        };
+
+        match request {
+            RelayedMessageRequest::Http(request) => {
+                let send = async move {
+                    let client = Client::new();
+                    let mut headers = HeaderMap::new();
+                    for (k, v) in &request.headers {
+                        let name = HeaderName::from_str(k)
+                            .map_err(|_| HttpRelayError::InvalidHeader(k.clone()))?;
+                        let value = HeaderValue::from_str(v)
+                            .map_err(|_| HttpRelayError::InvalidHeader(k.clone()))?;
+                        headers.insert(name, value);
+                    }
+                    let builder = match request.method {
+                        Method::Get => {
+                            let builder = client.get(&request.url).headers(headers);
+
+                            if let Some(body) = &request.body {
+                                builder.body(body.clone())
+                            } else {
+                                builder
+                            }
+                        }
+                        Method::Post => {
+                            let builder = client.post(&request.url).headers(headers);
+
+                            if let Some(body) = &request.body {
+                                builder.body(body.clone())
+                            } else {
+                                builder
+                            }
+                        }
+                    };
+                    builder
+                        .send()
+                        .await
+                        .map(|response| response.status())
+                        .map_err(HttpRelayError::Request)
+                };
 
-            for handle in handles {
-                let _ = handle.await;
+                // Requests run in their own task so that a panicking request
+                // is recorded as failed rather than aborting the relay.
+                let handle = tokio::spawn(send);
+                if deterministic {
+                    relayed.http_responses.push(http_outcome(handle.await))
+                } else {
+                    handles.push(handle)
+                }
             }
-        })
-        .await;
+            RelayedMessageRequest::Log(log) => relayed.logs.push(log),
+        }
+    }
 
-        invocation
+    for handle in handles {
+        relayed.http_responses.push(http_outcome(handle.await));
     }
+
+    relayed
+}
+
+/// Flattens the outcome of the task sending a relayed HTTP request.
+fn http_outcome(
+    outcome: Result<Result<StatusCode, HttpRelayError>, JoinError>,
+) -> Result<StatusCode, HttpRelayError> {
+    outcome.map_err(HttpRelayError::Task)?
 }
 
 /// Database handler object.
@@ -1,23 +1,86 @@
 use std::{
+    collections::HashSet,
     fmt,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 /// Wrapper around the trace implementation. None when stack is disable for memn-efficient mode, Some when enabled.
-#[derive(Clone, Debug)]
-pub struct StackTrace(Option<Vec<TraceImpl>>);
+///
+/// Serializes to a JSON array of traces (or `null` when disabled), see [`StackTrace::to_json`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StackTrace(
+    Option<Vec<TraceImpl>>,
+    #[serde(skip)] Redaction,
+    // Trace point categories to record, all categories are recorded when None.
+    #[serde(skip)] Option<HashSet<TracePoint>>,
+);
 
-#[derive(Clone, Debug)]
+/// Redaction applied to the program data included in traces and to the data
+/// of relayed logs, so that sensitive payloads (request bodies, tokens, keys)
+/// don't end up in traces and logs. The surrounding messages are kept as is,
+/// and so is data up to the given length.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Redaction {
+    /// Traces and logs are kept as is.
+    #[default]
+    Disabled,
+
+    /// Longer content is truncated to the given length.
+    Truncate(usize),
+
+    /// Longer content is replaced by its md5 digest.
+    Hash(usize),
+}
+
+impl Redaction {
+    /// Redacts a byte payload.
+    pub fn redact_bytes(&self, payload: Vec<u8>) -> Vec<u8> {
+        match *self {
+            Self::Truncate(max) if payload.len() > max => payload[..max].to_vec(),
+            Self::Hash(max) if payload.len() > max => md5::compute(&payload).0.to_vec(),
+            _ => payload,
+        }
+    }
+
+    /// Redacts the text representation of a payload.
+    pub fn redact_text(&self, payload: String) -> String {
+        match *self {
+            Self::Truncate(max) if payload.len() > max => {
+                let end = (0..=max)
+                    .rev()
+                    .find(|idx| payload.is_char_boundary(*idx))
+                    .unwrap_or(0);
+                format!("{} [redacted]", &payload[..end])
+            }
+            Self::Hash(max) if payload.len() > max => {
+                format!("[redacted {:x}]", md5::compute(payload.as_bytes()))
+            }
+            _ => payload,
+        }
+    }
+}
+
+/// Category of a trace, used to filter the recorded traces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TracePoint {
+    /// Soroban host environment and conversions.
     SorobanEnvironment,
+
+    /// Zephyr host environment.
     ZephyrEnvironment,
+
+    /// Database implementation.
     DatabaseImpl,
+
+    /// Ledger state implementation.
     LedgerImpl,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct TraceImpl {
     trace_point: TracePoint,
     time: u128,
@@ -28,7 +91,14 @@ struct TraceImpl {
 }
 
 impl StackTrace {
+    /// Records a trace if the stack trace is enabled and the trace point is not filtered out.
     pub fn maybe_add_trace(&mut self, point: TracePoint, message: impl ToString, is_error: bool) {
+        if let Some(points) = self.2.as_ref() {
+            if !points.contains(&point) {
+                return;
+            }
+        }
+
         if let Some(traces) = self.0.as_mut() {
             let start = SystemTime::now();
             let since_the_epoch = start
@@ -44,20 +114,70 @@ impl StackTrace {
         }
     }
 
+    /// Records a trace whose message includes program data, e.g. bytes read from
+    /// the guest's memory. Only the payload is redacted, `message` builds the
+    /// trace message around the payload's redacted debug representation.
+    pub fn maybe_add_payload_trace(
+        &mut self,
+        point: TracePoint,
+        payload: &impl fmt::Debug,
+        message: impl FnOnce(&str) -> String,
+        is_error: bool,
+    ) {
+        if self.0.is_none() {
+            return;
+        }
+
+        let payload = self.1.redact_text(format!("{:?}", payload));
+        self.maybe_add_trace(point, message(&payload), is_error)
+    }
+
+    /// Starts recording traces.
     pub fn enable(&mut self) {
         self.0 = Some(vec![])
     }
 
+    /// Stops recording traces and drops the recorded ones.
     pub fn disable(&mut self) {
         self.0 = None
     }
 
+    /// Only records traces of the given categories.
+    pub fn set_trace_points(&mut self, points: impl IntoIterator<Item = TracePoint>) {
+        self.2 = Some(points.into_iter().collect())
+    }
+
+    /// Records traces of all categories, which is the default.
+    pub fn enable_all_trace_points(&mut self) {
+        self.2 = None
+    }
+
+    /// Sets the redaction applied to the payloads of new traces.
+    pub fn set_redaction(&mut self, redaction: Redaction) {
+        self.1 = redaction
+    }
+
+    /// Returns the currently configured redaction.
+    pub fn redaction(&self) -> Redaction {
+        self.1
+    }
+
+    /// Returns the compact JSON representation of the stack trace.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Parses a stack trace from its JSON representation.
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
     // No method to clear the trace is needed for now.
 }
 
 impl Default for StackTrace {
     fn default() -> Self {
-        Self(None)
+        Self(None, Redaction::default(), None)
     }
 }
 
@@ -2,13 +2,22 @@
 //!
 
 use anyhow::{anyhow, Result};
-use std::{cell::RefCell, rc::Rc};
-use wasmi::{Engine, Instance, Linker, Memory, Module, StackLimits, Store};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+};
+use wasmi::{
+    core::TrapCode, Engine, ExternType, FuncType, Instance, Linker, Memory, Module, StackLimits,
+    Store,
+};
 
 use crate::{
     db::{database::ZephyrDatabase, ledger::LedgerStateRead},
     error::{HostError, InternalError},
-    host::{Host, InvokedFunctionInfo},
+    host::{FunctionInfo, Host, InvokedFunctionInfo},
+    trace::TracePoint,
 };
 
 const MIN_VALUE_STACK_HEIGHT: usize = 1024;
@@ -19,6 +28,76 @@ const MIN_VALUE_STACK_HEIGHT: usize = 1024;
 const MAX_VALUE_STACK_HEIGHT: usize = 2 * 1024 * MIN_VALUE_STACK_HEIGHT;
 const MAX_RECURSION_DEPTH: usize = 1024;
 
+thread_local! {
+    // Host functions of the host types instantiated on this thread, keyed by
+    // host type, see `Vm::host_functions`.
+    static HOST_FUNCTIONS: RefCell<HashMap<TypeId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// WebAssembly features and validation settings used when creating the VM.
+///
+/// Defaults match wasmi's defaults, which is what Zephyr programs are
+/// currently built against. Note that multi-value is required by the
+/// host functions ABI and should stay enabled for SDK-built programs.
+///
+/// SIMD is not supported by the VM's wasmi version, so binaries using
+/// SIMD instructions are always rejected.
+#[derive(Clone, Debug)]
+pub struct VmConfig {
+    /// Enables the bulk memory operations proposal.
+    pub bulk_memory: bool,
+
+    /// Enables the reference types proposal.
+    pub reference_types: bool,
+
+    /// Enables the multi-value proposal.
+    pub multi_value: bool,
+
+    /// Validates the binary before instantiation. Disabled features are
+    /// only enforced when the binary is validated, so binaries are always
+    /// validated when any feature is disabled.
+    pub validate: bool,
+
+    /// Name under which the binary exports its linear memory.
+    pub memory_export_name: String,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            bulk_memory: true,
+            reference_types: true,
+            multi_value: true,
+            validate: false,
+            memory_export_name: "memory".to_string(),
+        }
+    }
+}
+
+impl VmConfig {
+    fn apply(&self, config: &mut wasmi::Config) {
+        config.wasm_bulk_memory(self.bulk_memory);
+        config.wasm_reference_types(self.reference_types);
+        config.wasm_multi_value(self.multi_value);
+    }
+
+    /// Whether any of the features enabled by default is disabled.
+    fn restricts_features(&self) -> bool {
+        !self.bulk_memory || !self.reference_types || !self.multi_value
+    }
+
+    fn compile(&self, engine: &Engine, wasm_module_code_bytes: &[u8]) -> Result<Module> {
+        let module = if self.validate || self.restricts_features() {
+            Module::new(engine, wasm_module_code_bytes)?
+        } else {
+            // NOTE: This requires validation to occur upon deployment.
+            unsafe { Module::new_unchecked(engine, wasm_module_code_bytes)? }
+        };
+
+        Ok(module)
+    }
+}
+
 /// MemoryManager object. Stored in the VM object.
 #[derive(Clone)]
 pub struct MemoryManager {
@@ -40,6 +119,18 @@ impl MemoryManager {
     }
 }
 
+/// Host functions shared by the VMs of a host type, so that they are only
+/// created once rather than on every instantiation. Each VM still has its own
+/// engine and linker, so that dropping the VM releases its compiled code.
+pub(crate) struct HostFunctions<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + 'static>
+{
+    // Host functions in definition order, the first definition of a name wins.
+    functions: Vec<FunctionInfo<DB, L>>,
+
+    // Signatures of the host functions, by module and function name.
+    types: HashMap<(&'static str, &'static str), FuncType>,
+}
+
 /// The Zephyr VM.
 pub struct Vm<DB: ZephyrDatabase, L: LedgerStateRead> {
     /// VM's store object. Provides bindings to the host.
@@ -55,6 +146,16 @@ pub struct Vm<DB: ZephyrDatabase, L: LedgerStateRead> {
 impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static> Vm<DB, L> {
     /// Returns a ZVM handle given an in-memory wasm instance.
     pub fn new_from_initialized_module(host: &Host<DB, L>, instance: Instance) -> Result<Rc<Self>> {
+        Self::new_from_initialized_module_with_config(host, instance, &VmConfig::default())
+    }
+
+    /// Returns a ZVM handle given an in-memory wasm instance and the
+    /// configuration it was instantiated with.
+    pub fn new_from_initialized_module_with_config(
+        host: &Host<DB, L>,
+        instance: Instance,
+        vm_config: &VmConfig,
+    ) -> Result<Rc<Self>> {
         let mut config = wasmi::Config::default();
         let stack_limits = StackLimits::new(
             MIN_VALUE_STACK_HEIGHT,
@@ -63,8 +164,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
         )
         .map_err(|_| HostError::InternalError(InternalError::WasmiConfig))?;
 
-        // TODO: decide which post-mvp features to override.
-        // For now we use wasmtime's defaults.
+        vm_config.apply(&mut config);
         config.consume_fuel(true);
         config.set_stack_limits(stack_limits);
         config.compilation_mode(wasmi::CompilationMode::Eager);
@@ -77,18 +177,8 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
 
         // TODO: set Store::limiter() once host implements ResourceLimiter
 
-        let mut linker = <Linker<Host<DB, L>>>::new(&engine);
-
-        for func_info in host.host_functions(&mut store) {
-            // Note: this is just a current workaround.
-            let _ = linker.define(func_info.module, func_info.func, func_info.wrapped);
-        }
-
-        let memory = instance
-            .get_export(&mut store, "memory")
-            .ok_or_else(|| HostError::NoMemoryExport)?
-            .into_memory()
-            .ok_or_else(|| HostError::NoMemoryExport)?;
+        // No linker is needed here since the instance is already linked.
+        let memory = Self::memory_export(&mut store, &instance, &vm_config.memory_export_name)?;
 
         let memory_manager = MemoryManager::new(memory, 0);
 
@@ -104,24 +194,43 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
         host: &Host<DB, L>,
         wasm_module_code_bytes: &[u8],
     ) -> Result<Instance> {
-        let mut config = wasmi::Config::default();
-        let stack_limits = StackLimits::new(
-            MIN_VALUE_STACK_HEIGHT,
-            MAX_VALUE_STACK_HEIGHT,
-            MAX_RECURSION_DEPTH,
-        )
-        .map_err(|_| HostError::InternalError(InternalError::WasmiConfig))?;
+        Self::new_only_instance_with_config(host, wasm_module_code_bytes, &VmConfig::default())
+    }
 
-        // TODO: decide which post-mvp features to override.
-        // For now we use wasmtime's defaults.
-        config.consume_fuel(true);
-        config.set_stack_limits(stack_limits);
-        config.compilation_mode(wasmi::CompilationMode::Lazy);
+    /// Constructs and initializes a new wasm instance with the given
+    /// wasm features configuration.
+    pub fn new_only_instance_with_config(
+        host: &Host<DB, L>,
+        wasm_module_code_bytes: &[u8],
+        vm_config: &VmConfig,
+    ) -> Result<Instance> {
+        let engine = Self::engine(vm_config)?;
+        let module = vm_config.compile(&engine, wasm_module_code_bytes)?;
 
-        let engine = Engine::new(&config);
+        let mut store = Store::new(&engine, host.clone());
+        if let Err(error) = host.as_budget().infer_fuel(&mut store) {
+            return Err(anyhow!(error));
+        };
+
+        // TODO: set Store::limiter() once host implements ResourceLimiter
+
+        Self::instantiate(host, &mut store, &module)
+    }
+
+    /// Creates and instantiates the VM.
+    pub fn new(host: &Host<DB, L>, wasm_module_code_bytes: &[u8]) -> Result<Rc<Self>> {
+        Self::new_with_config(host, wasm_module_code_bytes, &VmConfig::default())
+    }
 
-        // NOTE: This requires validation to occur upon deployment.
-        let module = unsafe { Module::new_unchecked(&engine, wasm_module_code_bytes)? };
+    /// Creates and instantiates the VM with the given wasm features
+    /// configuration.
+    pub fn new_with_config(
+        host: &Host<DB, L>,
+        wasm_module_code_bytes: &[u8],
+        vm_config: &VmConfig,
+    ) -> Result<Rc<Self>> {
+        let engine = Self::engine(vm_config)?;
+        let module = vm_config.compile(&engine, wasm_module_code_bytes)?;
 
         let mut store = Store::new(&engine, host.clone());
         if let Err(error) = host.as_budget().infer_fuel(&mut store) {
@@ -130,23 +239,21 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
 
         // TODO: set Store::limiter() once host implements ResourceLimiter
 
-        let mut linker = <Linker<Host<DB, L>>>::new(&engine);
+        let instance = Self::instantiate(host, &mut store, &module)?;
+        let memory = Self::memory_export(&mut store, &instance, &vm_config.memory_export_name)?;
 
-        for func_info in host.host_functions(&mut store) {
-            // Note: this is just a current workaround.
-            let _ = linker.define(func_info.module, func_info.func, func_info.wrapped);
-        }
-
-        // NOTE
-        // We are not starting instance already.
-        let instance = linker.instantiate(&mut store, &module)?;
-        let instance = instance.start(&mut store)?; // handle
+        let memory_manager = MemoryManager::new(memory, 0);
 
-        Ok(instance)
+        Ok(Rc::new(Self {
+            store: RefCell::new(store),
+            memory_manager,
+            instance,
+        }))
     }
 
-    /// Creates and instantiates the VM.
-    pub fn new(host: &Host<DB, L>, wasm_module_code_bytes: &[u8]) -> Result<Rc<Self>> {
+    /// Creates the engine a VM compiles its binary with. Every VM gets its own
+    /// engine, which is dropped along with the code it compiled.
+    fn engine(vm_config: &VmConfig) -> Result<Engine> {
         let mut config = wasmi::Config::default();
         let stack_limits = StackLimits::new(
             MIN_VALUE_STACK_HEIGHT,
@@ -155,48 +262,197 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
         )
         .map_err(|_| HostError::InternalError(InternalError::WasmiConfig))?;
 
-        // TODO: decide which post-mvp features to override.
-        // For now we use wasmtime's defaults.
+        vm_config.apply(&mut config);
         config.consume_fuel(true);
         config.set_stack_limits(stack_limits);
         config.compilation_mode(wasmi::CompilationMode::Lazy);
 
-        let engine = Engine::new(&config);
+        Ok(Engine::new(&config))
+    }
 
-        // NOTE: This requires validation to occur upon deployment.
-        let module = unsafe { Module::new_unchecked(&engine, wasm_module_code_bytes)? };
+    /// Returns the host functions of the host type, created on the first call
+    /// on this thread and shared by the following instantiations.
+    pub(crate) fn host_functions(host: &Host<DB, L>) -> Rc<HostFunctions<DB, L>> {
+        let key = TypeId::of::<Host<DB, L>>();
+        let cached = HOST_FUNCTIONS.with(|cached| cached.borrow().get(&key).cloned());
+        if let Some(cached) =
+            cached.and_then(|cached| cached.downcast::<HostFunctions<DB, L>>().ok())
+        {
+            return cached;
+        }
 
-        let mut store = Store::new(&engine, host.clone());
-        if let Err(error) = host.as_budget().infer_fuel(&mut store) {
-            return Err(anyhow!(error));
-        };
+        let functions = Host::<DB, L>::host_functions();
 
-        // TODO: set Store::limiter() once host implements ResourceLimiter
+        // The signatures are read once from functions wrapped into a scratch
+        // store, the VMs' stores only hold the functions their module imports.
+        let mut store = Store::new(&Engine::default(), host.clone());
+        let mut types = HashMap::new();
+        for func_info in &functions {
+            types
+                .entry((func_info.module, func_info.func))
+                .or_insert_with(|| func_info.wrapped.wrap(&mut store).ty(&store));
+        }
 
-        let mut linker = <Linker<Host<DB, L>>>::new(&engine);
+        let cached = Rc::new(HostFunctions { functions, types });
+        HOST_FUNCTIONS.with(|functions| {
+            functions
+                .borrow_mut()
+                .insert(key, cached.clone() as Rc<dyn Any>)
+        });
 
-        for func_info in host.host_functions(&mut store) {
-            // Note: this is just a current workaround.
-            let _ = linker.define(func_info.module, func_info.func, func_info.wrapped);
-        }
+        cached
+    }
+
+    /// Instantiates and starts the module, linking the host functions it imports.
+    fn instantiate(
+        host: &Host<DB, L>,
+        store: &mut Store<Host<DB, L>>,
+        module: &Module,
+    ) -> Result<Instance> {
+        let host_functions = Self::host_functions(host);
+        Self::check_multi_value(module, &host_functions.types)?;
+
+        let linker = Self::linker(host, store, module, &host_functions.functions);
 
         // NOTE
         // We are not starting instance already.
-        let instance = linker.instantiate(&mut store, &module)?;
-        let instance = instance.start(&mut store)?; // handle
+        let instance = linker.instantiate(&mut *store, module)?;
+        let instance = instance.start(store)?; // handle
+
+        Ok(instance)
+    }
+
+    /// Builds the linker defining the host functions imported by the given module.
+    ///
+    /// Hosts counting host calls wrap the functions into the store instead,
+    /// since the counting wrapper is bound to the store, see
+    /// [`Host::set_max_host_calls`].
+    fn linker(
+        host: &Host<DB, L>,
+        store: &mut Store<Host<DB, L>>,
+        module: &Module,
+        functions: &[FunctionInfo<DB, L>],
+    ) -> Linker<Host<DB, L>> {
+        let mut linker = <Linker<Host<DB, L>>>::new(store.engine());
+        let imports: Vec<(&str, &str)> = module
+            .imports()
+            .filter(|import| matches!(import.ty(), ExternType::Func(_)))
+            .map(|import| (import.module(), import.name()))
+            .collect();
+
+        let counted = host.counts_host_calls();
+        for func_info in functions
+            .iter()
+            .filter(|func_info| imports.contains(&(func_info.module, func_info.func)))
+        {
+            // Note: this is just a current workaround.
+            if counted {
+                let wrapped = func_info.wrapped.wrap(store);
+                let wrapped = Host::counted_function(store, wrapped);
+                let _ = linker.define(func_info.module, func_info.func, wrapped);
+            } else {
+                let _ = func_info
+                    .wrapped
+                    .define(&mut linker, func_info.module, func_info.func);
+            }
+        }
+
+        linker
+    }
+
+    /// Returns the memory exported by the instance under the given name. Binaries
+    /// that don't export their memory are rejected with [`HostError::NoMemoryExport`]
+    /// since the host can't exchange data with them.
+    fn memory_export(
+        store: &mut Store<Host<DB, L>>,
+        instance: &Instance,
+        name: &str,
+    ) -> Result<Memory> {
         let memory = instance
-            .get_export(&mut store, "memory")
+            .get_export(store, name)
             .ok_or_else(|| HostError::NoMemoryExport)?
             .into_memory()
             .ok_or_else(|| HostError::NoMemoryExport)?;
 
-        let memory_manager = MemoryManager::new(memory, 0);
+        Ok(memory)
+    }
 
-        Ok(Rc::new(Self {
-            store: RefCell::new(store),
-            memory_manager,
-            instance,
-        }))
+    /// Ensures that the module imports the multi-value host functions with
+    /// their multi-value signatures. Guests compiled without the multi-value
+    /// proposal lower these returns to a return pointer, which would otherwise
+    /// only surface as a generic linking error.
+    fn check_multi_value(
+        module: &Module,
+        types: &HashMap<(&'static str, &'static str), FuncType>,
+    ) -> Result<()> {
+        for import in module.imports() {
+            let imported = match import.ty() {
+                ExternType::Func(imported) => imported,
+                _ => continue,
+            };
+
+            let expected = match types.get(&(import.module(), import.name())) {
+                Some(expected) => expected,
+                None => continue,
+            };
+
+            if expected.results().len() > 1 && expected != imported {
+                return Err(HostError::MultiValueRequired(import.name().to_string()).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the functions exported by the binary along with their signatures.
+    pub fn exported_functions(&self) -> Vec<(String, FuncType)> {
+        let store = self.store.borrow();
+
+        self.instance
+            .exports(&*store)
+            .filter_map(|export| {
+                let name = export.name().to_string();
+                export.into_func().map(|func| (name, func.ty(&*store)))
+            })
+            .collect()
+    }
+
+    /// Maps the error of a guest function call, distinguishing fuel exhaustion
+    /// and exceeded host call quotas or Soroban budgets from other traps so that
+    /// expensive programs aren't reported as crashes.
+    /// Traps are also recorded in the stack trace.
+    fn call_error(host: &Host<DB, L>, error: wasmi::Error) -> anyhow::Error {
+        let error = match error.as_trap_code() {
+            _ if host.host_call_quota_exceeded() => HostError::HostCallQuotaExceeded.into(),
+            _ if host.soroban_budget_exceeded() => HostError::SorobanBudgetExceeded.into(),
+            Some(TrapCode::OutOfFuel) => HostError::OutOfFuel.into(),
+            Some(code) => HostError::Trap(code).into(),
+            None => error.into(),
+        };
+
+        host.as_stack_trace_mut().maybe_add_trace(
+            TracePoint::ZephyrEnvironment,
+            format!("Program execution failed: {}.", error),
+            true,
+        );
+
+        error
+    }
+
+    /// Fails an invocation that exhausted the Soroban budget even if the program
+    /// ignored the errors of the conversions that exceeded it.
+    fn check_soroban_budget(host: &Host<DB, L>) -> Result<()> {
+        if !host.soroban_budget_exceeded() {
+            return Ok(());
+        }
+
+        host.as_stack_trace_mut().maybe_add_trace(
+            TracePoint::SorobanEnvironment,
+            "Program exceeded the Soroban budget.",
+            true,
+        );
+
+        Err(HostError::SorobanBudgetExceeded.into())
     }
 
     /// Entry point of a Zephyr VM invocation.
@@ -204,6 +460,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
     /// The function itself won't return anything but will have access to the Database
     /// implementation and the ledger metadata through Host bindings.
     pub fn metered_call(self: &Rc<Self>, host: &Host<DB, L>) -> Result<()> {
+        host.reset_invocation_state()?;
         let store = &self.store;
         let entry_point_info = host.get_entry_point_info();
         let mut retrn = entry_point_info.retrn.clone();
@@ -221,11 +478,18 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
             None => return Err(HostError::ExternNotAFunction.into()),
         };
 
-        func.call(
-            &mut *store.borrow_mut(),
-            entry_point_info.params.as_slice(),
-            &mut retrn,
-        )?;
+        let call = func
+            .call(
+                &mut *store.borrow_mut(),
+                entry_point_info.params.as_slice(),
+                &mut retrn,
+            )
+            .map_err(|error| Self::call_error(host, error));
+        // A failing flush must not hide the error of the program.
+        let flush = host.flush_repeated_logs();
+        call?;
+        flush?;
+        Self::check_soroban_budget(host)?;
 
         Ok(())
     }
@@ -236,6 +500,7 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
         host: &Host<DB, L>,
         fname: &str,
     ) -> Result<String> {
+        host.reset_invocation_state()?;
         let invoked_function_info = InvokedFunctionInfo::serverless_defaults(fname);
 
         let store: &RefCell<Store<Host<DB, L>>> = &self.store;
@@ -254,12 +519,41 @@ impl<DB: ZephyrDatabase + Clone + 'static, L: LedgerStateRead + Clone + 'static>
             None => return Err(HostError::ExternNotAFunction.into()),
         };
 
-        func.call(
-            &mut *store.borrow_mut(),
-            invoked_function_info.params.as_slice(),
-            &mut retrn,
-        )?;
+        let call = func
+            .call(
+                &mut *store.borrow_mut(),
+                invoked_function_info.params.as_slice(),
+                &mut retrn,
+            )
+            .map_err(|error| Self::call_error(host, error));
+        // A failing flush must not hide the error of the program.
+        let flush = host.flush_repeated_logs();
+        call?;
+        flush?;
+        Self::check_soroban_budget(host)?;
 
         Ok(host.read_result())
     }
+
+    /// Executes the requested exported functions of the binary in sequence on
+    /// the same instance, returning the result of each call.
+    ///
+    /// The host's result is cleared between calls, but the instance's linear
+    /// memory and globals are not: state left behind by a function (e.g. the
+    /// allocator's heap) is visible to the functions that follow it, so this
+    /// should only be used with programs whose functions don't rely on a fresh
+    /// instance. Execution stops at the first failing call.
+    pub fn metered_function_calls(
+        self: &Rc<Self>,
+        host: &Host<DB, L>,
+        fnames: &[&str],
+    ) -> Result<Vec<String>> {
+        let mut results = Vec::with_capacity(fnames.len());
+
+        for fname in fnames {
+            results.push(self.metered_function_call(host, fname)?);
+        }
+
+        Ok(results)
+    }
 }
@@ -0,0 +1,39 @@
+use zephyr_sdk::{prelude::*, DatabaseDerive, DatabaseInteract, EnvClient, SdkError};
+
+#[derive(DatabaseDerive)]
+#[with_name("hello")]
+pub struct Hello {
+    tdep: i32,
+    amount: i64,
+}
+
+#[no_mangle]
+pub extern "C" fn try_read() {
+    let env = EnvClient::empty();
+
+    if !matches!(
+        Hello::try_read_to_rows(&env),
+        Err((_, SdkError::Conversion))
+    ) {
+        panic!()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn try_read_invalid_column() {
+    let env = EnvClient::empty();
+
+    if !matches!(
+        Hello::try_read_to_rows(&env),
+        Err((Some(1), SdkError::Conversion))
+    ) {
+        panic!()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn read() {
+    let env = EnvClient::empty();
+
+    let _: Vec<Hello> = env.read();
+}